@@ -0,0 +1,195 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::RwLock;
+
+use super::rbtree::{Augmentation, Compare, Natural, RBTree};
+
+/// [`RBTree`] shared between threads. Readers run concurrently and writers take turns. Long
+/// scans go through [`scan`](Self::scan), which lets writers in between chunks instead of
+/// copying the tree, so every write stays O(log n) and handles stay valid.
+pub struct ConcurrentRBTree<T, A: Augmentation<T> = (), C: Compare<T> = Natural> {
+    tree: RwLock<RBTree<T, A, C>>,
+}
+
+impl<T: Ord> Default for ConcurrentRBTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> ConcurrentRBTree<T> {
+    pub fn new() -> Self {
+        Self::from_tree(RBTree::new())
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T>> ConcurrentRBTree<T, A, C> {
+    pub fn from_tree(tree: RBTree<T, A, C>) -> Self {
+        Self {
+            tree: RwLock::new(tree),
+        }
+    }
+
+    /// Runs `f` under the read lock
+    pub fn read<R>(&self, f: impl FnOnce(&RBTree<T, A, C>) -> R) -> R {
+        f(&self.tree.read().expect("RBTree lock poisoned"))
+    }
+
+    /// Runs `f` under the write lock
+    pub fn write<R>(&self, f: impl FnOnce(&mut RBTree<T, A, C>) -> R) -> R {
+        f(&mut self.tree.write().expect("RBTree lock poisoned"))
+    }
+
+    /// Visits every element in order, holding the read lock for `chunk_len` elements at a time
+    /// and releasing it in between. A chunk runs on past `chunk_len` while the elements stay
+    /// equal, because the next one resumes after the last element visited.
+    ///
+    /// This is not a snapshot: elements present for the whole scan are visited exactly once, but
+    /// ones inserted or removed by writers meanwhile may or may not be.
+    pub fn scan(&self, chunk_len: usize, mut f: impl FnMut(&T))
+    where
+        T: Clone,
+    {
+        assert!(chunk_len > 0, "Scan chunks must hold at least one element");
+
+        let mut resume_after = None;
+        loop {
+            let tree = self.tree.read().expect("RBTree lock poisoned");
+            let start = match resume_after.take() {
+                Some(last) => Bound::Excluded(last),
+                None => Bound::Unbounded,
+            };
+
+            let mut last = None;
+            for (visited, element) in tree.range((start, Bound::Unbounded)).enumerate() {
+                if visited >= chunk_len
+                    && last.is_some_and(|last| {
+                        tree.comparator().compare(last, element) != Ordering::Equal
+                    })
+                {
+                    resume_after = last.cloned();
+                    break;
+                }
+                f(element);
+                last = Some(element);
+            }
+
+            if resume_after.is_none() {
+                return;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.read(RBTree::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read(RBTree::is_empty)
+    }
+
+    pub fn insert(&self, element: T) {
+        self.write(|tree| tree.insert(element));
+    }
+
+    pub fn delete(&self, element: &T) -> bool {
+        self.write(|tree| tree.delete(element))
+    }
+
+    pub fn into_inner(self) -> RBTree<T, A, C> {
+        self.tree.into_inner().expect("RBTree lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::ConcurrentRBTree;
+    use crate::data_structures::rbtree::{HandleError, RBTree};
+
+    #[test]
+    fn trees_move_between_threads() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<RBTree<String>>();
+        assert_send_sync::<ConcurrentRBTree<String>>();
+
+        let tree: RBTree<i32> = (0..100).collect();
+        let sum = thread::spawn(move || tree.iter().sum::<i32>())
+            .join()
+            .unwrap();
+        assert_eq!(sum, 4950);
+    }
+
+    #[test]
+    fn handles_stay_valid_across_scans() {
+        let shared = ConcurrentRBTree::new();
+        let handle = shared.write(|tree| tree.insert_with_handle(1));
+        for val in 2..50 {
+            shared.insert(val);
+        }
+
+        let mut seen = Vec::new();
+        shared.scan(8, |val| seen.push(*val));
+        shared.insert(0);
+
+        assert_eq!(seen, (1..50).collect::<Vec<_>>());
+        assert_eq!(
+            shared.read(|tree| tree.get_by_handle(handle).copied()),
+            Ok(1)
+        );
+        assert_eq!(shared.write(|tree| tree.remove_by_handle(handle)), Ok(1));
+        assert_eq!(
+            shared.read(|tree| tree.get_by_handle(handle).copied()),
+            Err(HandleError::Stale)
+        );
+    }
+
+    #[test]
+    fn scan_finishes_runs_of_equal_elements() {
+        let tree = RBTree::by_key(|pair: &(u32, u32)| pair.0);
+        let shared = ConcurrentRBTree::from_tree(tree);
+        for val in 0..30 {
+            shared.insert((val / 10, val));
+        }
+
+        let mut seen = Vec::new();
+        shared.scan(3, |pair| seen.push(pair.1));
+        assert_eq!(seen, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn scans_see_everything_that_stays_put() {
+        let shared = ConcurrentRBTree::new();
+        for val in 0..100 {
+            shared.insert(val);
+        }
+
+        thread::scope(|scope| {
+            for writer in 0..4 {
+                let shared = &shared;
+                scope.spawn(move || {
+                    for val in 0..250 {
+                        shared.insert(1000 + writer * 250 + val);
+                    }
+                });
+            }
+
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut values = Vec::new();
+                    shared.scan(16, |val| values.push(*val));
+                    assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+                    assert_eq!(values[..100], (0..100).collect::<Vec<_>>());
+                });
+            }
+        });
+
+        assert!(shared.delete(&0));
+        assert_eq!(shared.len(), 1099);
+
+        let tree = shared.into_inner();
+        tree.validate().unwrap();
+        assert_eq!(tree.iter().next(), Some(&1));
+    }
+}
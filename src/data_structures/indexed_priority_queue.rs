@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::rbtree::{Compare, Handle, RBTree};
+
+/// Orders queue entries by priority alone, so equal priorities come out in the order they were
+/// pushed
+#[derive(Debug, Clone, Copy, Default)]
+struct ByPriority;
+
+impl<P: Ord, I> Compare<(P, I)> for ByPriority {
+    fn compare(&self, lhs: &(P, I), rhs: &(P, I)) -> Ordering {
+        lhs.0.cmp(&rhs.0)
+    }
+}
+
+/// Priority queue over ids, where the priority of a queued id can be changed or the id removed
+/// in O(log n). Both the lowest and the highest priority can be popped.
+pub struct IndexedPriorityQueue<
+    I: Hash + Eq + std::fmt::Debug + Clone,
+    P: Ord + std::fmt::Debug + Clone,
+> {
+    entries: RBTree<(P, I), (), ByPriority>,
+    handles: HashMap<I, Handle>,
+}
+
+impl<I: Hash + Eq + std::fmt::Debug + Clone, P: Ord + std::fmt::Debug + Clone> Default
+    for IndexedPriorityQueue<I, P>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Hash + Eq + std::fmt::Debug + Clone, P: Ord + std::fmt::Debug + Clone>
+    IndexedPriorityQueue<I, P>
+{
+    pub fn new() -> Self {
+        Self {
+            entries: RBTree::with_comparator(ByPriority),
+            handles: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, id: &I) -> bool {
+        self.handles.contains_key(id)
+    }
+
+    pub fn priority(&self, id: &I) -> Option<&P> {
+        let handle = *self.handles.get(id)?;
+        Some(&self.get(handle).0)
+    }
+
+    /// Queues `id`, or moves it to `priority` if it is already queued, returning the old one
+    pub fn push(&mut self, id: I, priority: P) -> Option<P> {
+        let old = self.remove(&id);
+        let handle = self.entries.insert_with_handle((priority, id.clone()));
+        self.handles.insert(id, handle);
+        old
+    }
+
+    /// Moves a queued `id` to `priority`, returning the old one. Does nothing if `id` is not
+    /// queued.
+    pub fn change_priority(&mut self, id: &I, priority: P) -> Option<P> {
+        if !self.contains(id) {
+            return None;
+        }
+
+        self.push(id.clone(), priority)
+    }
+
+    /// Takes `id` out of the queue, returning its priority
+    pub fn remove(&mut self, id: &I) -> Option<P> {
+        let handle = self.handles.remove(id)?;
+        let (priority, _) = self
+            .entries
+            .remove_by_handle(handle)
+            .expect("queue handles stay valid while their ids are queued");
+        Some(priority)
+    }
+
+    /// The id with the lowest priority, the earliest pushed among equals
+    pub fn peek(&self) -> Option<(&I, &P)> {
+        self.entries.first().map(|(priority, id)| (id, priority))
+    }
+
+    /// The id with the highest priority, the latest pushed among equals
+    pub fn peek_max(&self) -> Option<(&I, &P)> {
+        self.entries.last().map(|(priority, id)| (id, priority))
+    }
+
+    pub fn pop_min(&mut self) -> Option<(I, P)> {
+        let (priority, id) = self.entries.pop_first()?;
+        self.handles.remove(&id);
+        Some((id, priority))
+    }
+
+    pub fn pop_max(&mut self) -> Option<(I, P)> {
+        let (priority, id) = self.entries.pop_last()?;
+        self.handles.remove(&id);
+        Some((id, priority))
+    }
+
+    /// Queued ids from the lowest priority to the highest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&I, &P)> + ExactSizeIterator {
+        self.entries.iter().map(|(priority, id)| (id, priority))
+    }
+
+    fn get(&self, handle: Handle) -> &(P, I) {
+        self.entries
+            .get_by_handle(handle)
+            .expect("queue handles stay valid while their ids are queued")
+    }
+}
+
+impl<I: Hash + Eq + std::fmt::Debug + Clone, P: Ord + std::fmt::Debug + Clone> Extend<(I, P)>
+    for IndexedPriorityQueue<I, P>
+{
+    fn extend<T: IntoIterator<Item = (I, P)>>(&mut self, iter: T) {
+        for (id, priority) in iter {
+            self.push(id, priority);
+        }
+    }
+}
+
+impl<I: Hash + Eq + std::fmt::Debug + Clone, P: Ord + std::fmt::Debug + Clone> FromIterator<(I, P)>
+    for IndexedPriorityQueue<I, P>
+{
+    fn from_iter<T: IntoIterator<Item = (I, P)>>(iter: T) -> Self {
+        let mut queue = Self::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndexedPriorityQueue;
+
+    #[test]
+    fn pops_from_both_ends() {
+        let mut queue: IndexedPriorityQueue<&str, u32> =
+            [("c", 30), ("a", 10), ("d", 40), ("b", 20)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.peek(), Some((&"a", &10)));
+        assert_eq!(queue.peek_max(), Some((&"d", &40)));
+        assert_eq!(queue.pop_min(), Some(("a", 10)));
+        assert_eq!(queue.pop_max(), Some(("d", 40)));
+        assert!(!queue.contains(&"a"));
+        assert_eq!(
+            queue.iter().collect::<Vec<_>>(),
+            vec![(&"b", &20), (&"c", &30)]
+        );
+    }
+
+    #[test]
+    fn change_priority_and_remove_by_id() {
+        let mut queue = IndexedPriorityQueue::new();
+        for id in 0..10 {
+            queue.push(id, 100 - id);
+        }
+
+        assert_eq!(queue.change_priority(&3, 0), Some(97));
+        assert_eq!(queue.change_priority(&42, 0), None);
+        assert_eq!(queue.push(5, 200), Some(95));
+        assert_eq!(queue.priority(&5), Some(&200));
+        assert_eq!(queue.remove(&9), Some(91));
+        assert_eq!(queue.remove(&9), None);
+
+        assert_eq!(queue.len(), 9);
+        assert_eq!(queue.pop_min(), Some((3, 0)));
+        assert_eq!(queue.pop_max(), Some((5, 200)));
+        assert_eq!(queue.pop_min(), Some((8, 92)));
+    }
+
+    #[test]
+    fn equal_priorities_pop_in_push_order() {
+        let mut queue = IndexedPriorityQueue::new();
+        for id in ["first", "second", "third"] {
+            queue.push(id, 1);
+        }
+        queue.change_priority(&"first", 1);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop_min()).collect();
+        assert_eq!(order, vec![("second", 1), ("third", 1), ("first", 1)]);
+    }
+
+    #[test]
+    fn dijkstra_with_decrease_key() {
+        let edges: &[&[(usize, u32)]] = &[
+            &[(1, 7), (2, 9), (5, 14)],
+            &[(0, 7), (2, 10), (3, 15)],
+            &[(0, 9), (1, 10), (3, 11), (5, 2)],
+            &[(1, 15), (2, 11), (4, 6)],
+            &[(3, 6), (5, 9)],
+            &[(0, 14), (2, 2), (4, 9)],
+        ];
+
+        let mut distances = [u32::MAX; 6];
+        let mut queue = IndexedPriorityQueue::new();
+        queue.push(0, 0);
+        while let Some((node, distance)) = queue.pop_min() {
+            distances[node] = distance;
+            for &(next, weight) in edges[node] {
+                let candidate = distance + weight;
+                if distances[next] == u32::MAX
+                    && queue.priority(&next).is_none_or(|&known| candidate < known)
+                {
+                    queue.push(next, candidate);
+                }
+            }
+        }
+
+        assert_eq!(distances, [0, 7, 9, 20, 20, 11]);
+    }
+}
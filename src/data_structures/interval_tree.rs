@@ -0,0 +1,314 @@
+use std::cmp::Ordering;
+use std::ops::{Bound, Range};
+
+use super::rbtree::{self, Augmentation, Node, RBTree};
+
+#[derive(Debug, Clone)]
+struct IntervalEntry<K, V> {
+    interval: Range<K>,
+    value: V,
+}
+
+impl<K: Ord, V> IntervalEntry<K, V> {
+    fn cmp_interval(&self, interval: &Range<K>) -> Ordering {
+        self.interval
+            .start
+            .cmp(&interval.start)
+            .then_with(|| self.interval.end.cmp(&interval.end))
+    }
+}
+
+impl<K: Ord, V> PartialEq for IntervalEntry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for IntervalEntry<K, V> {}
+
+impl<K: Ord, V> PartialOrd for IntervalEntry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for IntervalEntry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_interval(&other.interval)
+    }
+}
+
+/// Largest interval end in each subtree
+struct MaxEnd;
+
+impl<K: Ord + Clone, V> Augmentation<IntervalEntry<K, V>> for MaxEnd {
+    type Value = Option<K>;
+
+    fn empty() -> Self::Value {
+        None
+    }
+
+    fn lift(element: &IntervalEntry<K, V>) -> Self::Value {
+        Some(element.interval.end.clone())
+    }
+
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value {
+        left.as_ref().max(right.as_ref()).cloned()
+    }
+}
+
+/// Half-open `[start, end)` intervals ordered by start, each carrying a value. Equal intervals may
+/// be stored more than once.
+pub struct IntervalTree<K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> {
+    tree: RBTree<IntervalEntry<K, V>, MaxEnd>,
+}
+
+impl<K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> Default for IntervalTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> IntervalTree<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: RBTree::new_augmented(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Panics if the interval is empty
+    pub fn insert(&mut self, interval: Range<K>, value: V) {
+        assert!(
+            interval.start < interval.end,
+            "Interval {interval:?} is empty"
+        );
+        self.tree.insert(IntervalEntry { interval, value });
+    }
+
+    /// Removes one copy of exactly `interval`
+    pub fn remove(&mut self, interval: &Range<K>) -> Option<V> {
+        self.tree
+            .remove_by(|entry| {
+                interval
+                    .start
+                    .cmp(&entry.interval.start)
+                    .then_with(|| interval.end.cmp(&entry.interval.end))
+            })
+            .map(|entry| entry.value)
+    }
+
+    /// Every stored interval sharing at least one point with `query`, ordered by start
+    pub fn overlapping(&self, query: Range<K>) -> Overlapping<'_, K, V> {
+        if query.start >= query.end {
+            return Overlapping::empty();
+        }
+
+        Overlapping::new(
+            self.tree.root_node(),
+            query.start,
+            Bound::Excluded(query.end),
+        )
+    }
+
+    /// Every stored interval with `start <= point < end`, ordered by start
+    pub fn containing(&self, point: K) -> Overlapping<'_, K, V> {
+        Overlapping::new(self.tree.root_node(), point.clone(), Bound::Included(point))
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            entries: self.tree.iter(),
+        }
+    }
+}
+
+pub struct Iter<'a, K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> {
+    entries: rbtree::Iter<'a, IntervalEntry<K, V>, MaxEnd>,
+}
+
+impl<'a, K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> Iterator for Iter<'a, K, V> {
+    type Item = (&'a Range<K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next()
+            .map(|entry| (&entry.interval, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+/// Lazy in-order walk that skips every subtree whose largest end is not past `low`, and stops at
+/// the first interval starting beyond `high`
+pub struct Overlapping<'a, K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> {
+    stack: Vec<&'a Node<IntervalEntry<K, V>, MaxEnd>>,
+    low: Option<K>,
+    high: Bound<K>,
+}
+
+impl<'a, K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> Overlapping<'a, K, V> {
+    fn new(root: Option<&'a Node<IntervalEntry<K, V>, MaxEnd>>, low: K, high: Bound<K>) -> Self {
+        let mut overlapping = Self {
+            stack: Vec::new(),
+            low: Some(low),
+            high,
+        };
+        overlapping.push_left(root);
+        overlapping
+    }
+
+    fn empty() -> Self {
+        Self {
+            stack: Vec::new(),
+            low: None,
+            high: Bound::Unbounded,
+        }
+    }
+
+    fn push_left(&mut self, mut node: Option<&'a Node<IntervalEntry<K, V>, MaxEnd>>) {
+        while let Some(current) = node
+            && current.aggregate().as_ref() > self.low.as_ref()
+        {
+            self.stack.push(current);
+            node = current.left_node();
+        }
+    }
+
+    fn starts_too_late(&self, start: &K) -> bool {
+        match &self.high {
+            Bound::Included(high) => start > high,
+            Bound::Excluded(high) => start >= high,
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<'a, K: Ord + std::fmt::Debug + Clone, V: std::fmt::Debug + Clone> Iterator
+    for Overlapping<'a, K, V>
+{
+    type Item = (&'a Range<K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let entry = &node.value;
+
+            // Everything left on the stack comes later in order, so it starts even later
+            if self.starts_too_late(&entry.interval.start) {
+                self.stack.clear();
+                return None;
+            }
+
+            self.push_left(node.right_node());
+
+            if Some(&entry.interval.end) > self.low.as_ref() {
+                return Some((&entry.interval, &entry.value));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use super::IntervalTree;
+
+    fn brute_force_overlaps(intervals: &[Range<i32>], query: &Range<i32>) -> Vec<Range<i32>> {
+        let mut overlaps: Vec<Range<i32>> = intervals
+            .iter()
+            .filter(|interval| interval.start < query.end && query.start < interval.end)
+            .cloned()
+            .collect();
+        overlaps.sort_by_key(|interval| (interval.start, interval.end));
+        overlaps
+    }
+
+    #[test]
+    fn finds_overlapping_slots() {
+        let mut slots = IntervalTree::new();
+        slots.insert(9..10, "standup");
+        slots.insert(10..12, "review");
+        slots.insert(13..14, "lunch");
+        slots.insert(11..15, "workshop");
+
+        let overlaps: Vec<&str> = slots.overlapping(11..13).map(|(_, name)| *name).collect();
+        assert_eq!(overlaps, vec!["review", "workshop"]);
+
+        assert_eq!(slots.overlapping(12..13).count(), 1);
+        assert_eq!(slots.overlapping(15..20).count(), 0);
+        assert_eq!(slots.overlapping(8..9).count(), 0);
+        assert_eq!(slots.overlapping(10..10).count(), 0);
+    }
+
+    #[test]
+    fn containing_point_is_half_open() {
+        let mut tree = IntervalTree::new();
+        tree.insert(0..5, 'a');
+        tree.insert(5..10, 'b');
+        tree.insert(3..7, 'c');
+
+        let at = |point| tree.containing(point).map(|(_, v)| *v).collect::<Vec<_>>();
+
+        assert_eq!(at(5), vec!['c', 'b']);
+        assert_eq!(at(0), vec!['a']);
+        assert_eq!(at(10), Vec::<char>::new());
+    }
+
+    #[test]
+    fn remove_specific_interval() {
+        let mut tree = IntervalTree::new();
+        tree.insert(1..4, 1);
+        tree.insert(1..6, 2);
+        tree.insert(1..4, 3);
+
+        assert!(tree.remove(&(1..5)).is_none());
+        assert!(tree.remove(&(1..6)).is_some());
+        assert!(tree.remove(&(1..4)).is_some());
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.overlapping(5..6).count(), 0);
+        assert_eq!(tree.overlapping(3..4).count(), 1);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
+        let mut tree = IntervalTree::new();
+        let mut intervals: Vec<Range<i32>> = (0..200)
+            .map(|_| {
+                let start = rng.random_range(0..1000);
+                start..start + rng.random_range(1..80)
+            })
+            .collect();
+
+        for interval in &intervals {
+            tree.insert(interval.clone(), ());
+        }
+        for interval in intervals.drain(..60) {
+            assert!(tree.remove(&interval).is_some());
+        }
+
+        for _ in 0..100 {
+            let start = rng.random_range(0..1000);
+            let query = start..start + rng.random_range(1..50);
+            let found: Vec<Range<i32>> = tree
+                .overlapping(query.clone())
+                .map(|(interval, _)| interval.clone())
+                .collect();
+            assert_eq!(found, brute_force_overlaps(&intervals, &query));
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Color {
+    Red,
+    Black,
+}
+
+type Tree<T> = Option<Arc<Node<T>>>;
+
+struct Node<T> {
+    color: Color,
+    left: Tree<T>,
+    value: T,
+    right: Tree<T>,
+}
+
+fn red<T>(left: Tree<T>, value: T, right: Tree<T>) -> Tree<T> {
+    Some(Arc::new(Node {
+        color: Color::Red,
+        left,
+        value,
+        right,
+    }))
+}
+
+fn black<T>(left: Tree<T>, value: T, right: Tree<T>) -> Tree<T> {
+    Some(Arc::new(Node {
+        color: Color::Black,
+        left,
+        value,
+        right,
+    }))
+}
+
+/// The node at the top of `tree` if it is red
+fn as_red<T>(tree: &Tree<T>) -> Option<&Arc<Node<T>>> {
+    tree.as_ref().filter(|node| node.color == Color::Red)
+}
+
+/// The node at the top of `tree` if it is black, nil not included
+fn as_black<T>(tree: &Tree<T>) -> Option<&Arc<Node<T>>> {
+    tree.as_ref().filter(|node| node.color == Color::Black)
+}
+
+/// Immutable red-black tree where every update returns a new version. Versions share all nodes
+/// off the updated path through `Arc`s, so cloning one is O(1) and old versions stay readable.
+/// Equal elements may be stored more than once.
+///
+/// Balancing follows Kahrs, "Red-black trees with types" (2001).
+pub struct PersistentRBTree<T: Ord + std::fmt::Debug + Clone> {
+    root: Tree<T>,
+    len: usize,
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> Clone for PersistentRBTree<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> Default for PersistentRBTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> PersistentRBTree<T> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, element: &T) -> Option<&T> {
+        let mut traverse = &self.root;
+        while let Some(node) = traverse {
+            traverse = match element.cmp(&node.value) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(&node.value),
+            };
+        }
+
+        None
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.get(element).is_some()
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(left) = &node.left {
+            node = left;
+        }
+        Some(&node.value)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some(&node.value)
+    }
+
+    /// A new version holding `element` as well
+    pub fn insert(&self, element: T) -> Self {
+        Self {
+            root: Self::blacken(Self::insert_into(&self.root, element)),
+            len: self.len + 1,
+        }
+    }
+
+    /// A new version with one copy of `element` removed, or a clone of this one if it is absent
+    pub fn remove(&self, element: &T) -> Self {
+        if !self.contains(element) {
+            return self.clone();
+        }
+
+        Self {
+            root: Self::blacken(Self::remove_from(&self.root, element)),
+            len: self.len - 1,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    fn blacken(tree: Tree<T>) -> Tree<T> {
+        match as_red(&tree) {
+            Some(node) => black(node.left.clone(), node.value.clone(), node.right.clone()),
+            None => tree,
+        }
+    }
+
+    fn insert_into(tree: &Tree<T>, element: T) -> Tree<T> {
+        let Some(node) = tree else {
+            return red(None, element, None);
+        };
+
+        let goes_left = element < node.value;
+        match (node.color, goes_left) {
+            (Color::Black, true) => Self::balance(
+                Self::insert_into(&node.left, element),
+                node.value.clone(),
+                node.right.clone(),
+            ),
+            (Color::Black, false) => Self::balance(
+                node.left.clone(),
+                node.value.clone(),
+                Self::insert_into(&node.right, element),
+            ),
+            (Color::Red, true) => red(
+                Self::insert_into(&node.left, element),
+                node.value.clone(),
+                node.right.clone(),
+            ),
+            (Color::Red, false) => red(
+                node.left.clone(),
+                node.value.clone(),
+                Self::insert_into(&node.right, element),
+            ),
+        }
+    }
+
+    /// Builds a black node, resolving a red-red pair below it by rotating it into a red node
+    /// with two black children
+    fn balance(left: Tree<T>, value: T, right: Tree<T>) -> Tree<T> {
+        if let (Some(l), Some(r)) = (as_red(&left), as_red(&right)) {
+            return red(
+                black(l.left.clone(), l.value.clone(), l.right.clone()),
+                value,
+                black(r.left.clone(), r.value.clone(), r.right.clone()),
+            );
+        }
+
+        if let Some(l) = as_red(&left) {
+            if let Some(ll) = as_red(&l.left) {
+                return red(
+                    black(ll.left.clone(), ll.value.clone(), ll.right.clone()),
+                    l.value.clone(),
+                    black(l.right.clone(), value, right),
+                );
+            }
+            if let Some(lr) = as_red(&l.right) {
+                return red(
+                    black(l.left.clone(), l.value.clone(), lr.left.clone()),
+                    lr.value.clone(),
+                    black(lr.right.clone(), value, right),
+                );
+            }
+        }
+
+        if let Some(r) = as_red(&right) {
+            if let Some(rr) = as_red(&r.right) {
+                return red(
+                    black(left, value, r.left.clone()),
+                    r.value.clone(),
+                    black(rr.left.clone(), rr.value.clone(), rr.right.clone()),
+                );
+            }
+            if let Some(rl) = as_red(&r.left) {
+                return red(
+                    black(left, value, rl.left.clone()),
+                    rl.value.clone(),
+                    black(rl.right.clone(), r.value.clone(), r.right.clone()),
+                );
+            }
+        }
+
+        black(left, value, right)
+    }
+
+    /// Removes one copy of `element`, which must be present. Removing from a black subtree
+    /// lowers its black height by one, which the `balance_*` helpers make up for.
+    fn remove_from(tree: &Tree<T>, element: &T) -> Tree<T> {
+        let node = tree.as_ref().expect("Removed element is in the tree");
+
+        match element.cmp(&node.value) {
+            Ordering::Less if as_black(&node.left).is_some() => Self::balance_left(
+                Self::remove_from(&node.left, element),
+                node.value.clone(),
+                node.right.clone(),
+            ),
+            Ordering::Less => red(
+                Self::remove_from(&node.left, element),
+                node.value.clone(),
+                node.right.clone(),
+            ),
+            Ordering::Greater if as_black(&node.right).is_some() => Self::balance_right(
+                node.left.clone(),
+                node.value.clone(),
+                Self::remove_from(&node.right, element),
+            ),
+            Ordering::Greater => red(
+                node.left.clone(),
+                node.value.clone(),
+                Self::remove_from(&node.right, element),
+            ),
+            Ordering::Equal => Self::fuse(&node.left, &node.right),
+        }
+    }
+
+    /// Rebuilds a node whose left subtree is one black node short
+    fn balance_left(left: Tree<T>, value: T, right: Tree<T>) -> Tree<T> {
+        if let Some(l) = as_red(&left) {
+            return red(
+                black(l.left.clone(), l.value.clone(), l.right.clone()),
+                value,
+                right,
+            );
+        }
+
+        if let Some(r) = as_black(&right) {
+            return Self::balance(
+                left,
+                value,
+                red(r.left.clone(), r.value.clone(), r.right.clone()),
+            );
+        }
+
+        let r = as_red(&right).expect("Sibling of a shortened subtree is not nil");
+        let rl = as_black(&r.left).expect("Red sibling has black children");
+        red(
+            black(left, value, rl.left.clone()),
+            rl.value.clone(),
+            Self::balance(rl.right.clone(), r.value.clone(), Self::redden(&r.right)),
+        )
+    }
+
+    /// Rebuilds a node whose right subtree is one black node short
+    fn balance_right(left: Tree<T>, value: T, right: Tree<T>) -> Tree<T> {
+        if let Some(r) = as_red(&right) {
+            return red(
+                left,
+                value,
+                black(r.left.clone(), r.value.clone(), r.right.clone()),
+            );
+        }
+
+        if let Some(l) = as_black(&left) {
+            return Self::balance(
+                red(l.left.clone(), l.value.clone(), l.right.clone()),
+                value,
+                right,
+            );
+        }
+
+        let l = as_red(&left).expect("Sibling of a shortened subtree is not nil");
+        let lr = as_black(&l.right).expect("Red sibling has black children");
+        red(
+            Self::balance(Self::redden(&l.left), l.value.clone(), lr.left.clone()),
+            lr.value.clone(),
+            black(lr.right.clone(), value, right),
+        )
+    }
+
+    fn redden(tree: &Tree<T>) -> Tree<T> {
+        let node = as_black(tree).expect("Only black nodes are reddened");
+        red(node.left.clone(), node.value.clone(), node.right.clone())
+    }
+
+    /// Merges the two subtrees of a removed node, every element of `left` coming first
+    fn fuse(left: &Tree<T>, right: &Tree<T>) -> Tree<T> {
+        let (Some(l), Some(r)) = (left, right) else {
+            return left.clone().or_else(|| right.clone());
+        };
+
+        match (l.color, r.color) {
+            (Color::Red, Color::Red) => {
+                let middle = Self::fuse(&l.right, &r.left);
+                match as_red(&middle) {
+                    Some(m) => red(
+                        red(l.left.clone(), l.value.clone(), m.left.clone()),
+                        m.value.clone(),
+                        red(m.right.clone(), r.value.clone(), r.right.clone()),
+                    ),
+                    None => red(
+                        l.left.clone(),
+                        l.value.clone(),
+                        red(middle, r.value.clone(), r.right.clone()),
+                    ),
+                }
+            }
+            (Color::Black, Color::Black) => {
+                let middle = Self::fuse(&l.right, &r.left);
+                match as_red(&middle) {
+                    Some(m) => red(
+                        black(l.left.clone(), l.value.clone(), m.left.clone()),
+                        m.value.clone(),
+                        black(m.right.clone(), r.value.clone(), r.right.clone()),
+                    ),
+                    None => Self::balance_left(
+                        l.left.clone(),
+                        l.value.clone(),
+                        black(middle, r.value.clone(), r.right.clone()),
+                    ),
+                }
+            }
+            (Color::Black, Color::Red) => {
+                red(Self::fuse(left, &r.left), r.value.clone(), r.right.clone())
+            }
+            (Color::Red, Color::Black) => {
+                red(l.left.clone(), l.value.clone(), Self::fuse(&l.right, right))
+            }
+        }
+    }
+}
+
+impl<'a, T: Ord + std::fmt::Debug + Clone> IntoIterator for &'a PersistentRBTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn push_left(&mut self, mut tree: &'a Tree<T>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use super::{Color, Node, PersistentRBTree, Tree};
+
+    /// Checks the colouring and ordering, returning the black height
+    fn check<T: Ord>(tree: &Tree<T>, parent_red: bool) -> usize {
+        match tree {
+            Some(node) => {
+                let is_red = node.color == Color::Red;
+                assert!(!(parent_red && is_red), "red node with red parent");
+                if let Some(left) = &node.left {
+                    assert!(left.value <= node.value);
+                }
+                if let Some(right) = &node.right {
+                    assert!(right.value >= node.value);
+                }
+                let left_height = check(&node.left, is_red);
+                assert_eq!(left_height, check(&node.right, is_red));
+                left_height + !is_red as usize
+            }
+            None => 0,
+        }
+    }
+
+    fn assert_valid(tree: &PersistentRBTree<i32>) {
+        assert!(
+            tree.root
+                .as_ref()
+                .is_none_or(|root| root.color == Color::Black)
+        );
+        check(&tree.root, false);
+        assert_eq!(tree.iter().count(), tree.len());
+    }
+
+    fn nodes(tree: &Tree<i32>, found: &mut HashSet<*const Node<i32>>) {
+        if let Some(node) = tree {
+            found.insert(Arc::as_ptr(node));
+            nodes(&node.left, found);
+            nodes(&node.right, found);
+        }
+    }
+
+    #[test]
+    fn old_versions_stay_readable() {
+        let empty = PersistentRBTree::new();
+        let one = empty.insert(1);
+        let two = one.insert(2);
+        let without_one = two.remove(&1);
+
+        assert!(empty.is_empty());
+        assert_eq!(one.iter().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(two.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(without_one.iter().copied().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(without_one.remove(&7).len(), 1);
+        assert_eq!(two.first(), Some(&1));
+        assert_eq!(two.last(), Some(&2));
+    }
+
+    #[test]
+    fn updates_share_untouched_nodes() {
+        let mut tree = PersistentRBTree::new();
+        for val in 0..1024 {
+            tree = tree.insert(val);
+        }
+
+        let mut before = HashSet::new();
+        nodes(&tree.root, &mut before);
+        for updated in [tree.insert(500), tree.remove(&500)] {
+            let mut after = HashSet::new();
+            nodes(&updated.root, &mut after);
+            assert!(after.difference(&before).count() < 40);
+        }
+    }
+
+    #[test]
+    fn matches_sorted_vec() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
+        let mut versions = vec![(PersistentRBTree::new(), Vec::new())];
+
+        for _ in 0..2000 {
+            let (tree, values) = versions.choose(&mut rng).unwrap().clone();
+            let value = rng.random_range(0..200);
+            let (tree, mut values) = if rng.random_bool(0.6) {
+                let mut values = values;
+                values.push(value);
+                (tree.insert(value), values)
+            } else {
+                let mut values = values;
+                if let Some(position) = values.iter().position(|val| *val == value) {
+                    values.remove(position);
+                }
+                (tree.remove(&value), values)
+            };
+
+            values.sort();
+            assert_valid(&tree);
+            assert_eq!(tree.iter().copied().collect::<Vec<_>>(), values);
+            versions.push((tree, values));
+        }
+
+        for (tree, values) in &versions {
+            assert_eq!(tree.iter().copied().collect::<Vec<_>>(), *values);
+        }
+    }
+}
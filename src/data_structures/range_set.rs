@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use super::rbtree::{self, Compare, RBTree};
+
+/// Orders the stored ranges by where they start, which is enough while they are disjoint
+#[derive(Debug, Clone, Copy, Default)]
+struct ByStart;
+
+impl<T: Ord> Compare<Range<T>> for ByStart {
+    fn compare(&self, lhs: &Range<T>, rhs: &Range<T>) -> Ordering {
+        lhs.start.cmp(&rhs.start)
+    }
+}
+
+/// Union of half-open ranges, stored as the fewest disjoint, non-adjacent, non-empty ranges
+pub struct RangeSet<T: Ord + std::fmt::Debug + Clone> {
+    ranges: RBTree<Range<T>, (), ByStart>,
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> Default for RangeSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> RangeSet<T> {
+    pub fn new() -> Self {
+        Self {
+            ranges: RBTree::with_comparator(ByStart),
+        }
+    }
+
+    /// Number of disjoint ranges
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Adds every value in `range`, merging it with the ranges it overlaps or touches
+    pub fn insert(&mut self, range: Range<T>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let Range { mut start, mut end } = range;
+        let mut cursor = self.ranges.lower_bound_mut(&point(&start));
+        cursor.move_prev();
+        if cursor.current().is_none_or(|prev| prev.end < start) {
+            cursor.move_next();
+        }
+
+        while let Some(current) = cursor.current() {
+            if current.start > end {
+                break;
+            }
+            let merged = cursor.remove_current().unwrap();
+            start = start.min(merged.start);
+            end = end.max(merged.end);
+        }
+
+        self.ranges.insert(start..end);
+    }
+
+    /// Drops every value in `range`, splitting the ranges it cuts through
+    pub fn remove(&mut self, range: Range<T>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut leftovers = Vec::new();
+        let mut cursor = self.ranges.lower_bound_mut(&point(&range.start));
+        cursor.move_prev();
+        if cursor.current().is_none_or(|prev| prev.end <= range.start) {
+            cursor.move_next();
+        }
+
+        while let Some(current) = cursor.current() {
+            if current.start >= range.end {
+                break;
+            }
+            let cut = cursor.remove_current().unwrap();
+            if cut.start < range.start {
+                leftovers.push(cut.start..range.start.clone());
+            }
+            if cut.end > range.end {
+                leftovers.push(range.end.clone()..cut.end);
+            }
+        }
+
+        for leftover in leftovers {
+            self.ranges.insert(leftover);
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.range_containing(value).is_some()
+    }
+
+    /// The stored range `value` falls in
+    pub fn range_containing(&self, value: &T) -> Option<&Range<T>> {
+        self.ranges
+            .floor(&point(value))
+            .filter(|range| range.contains(value))
+    }
+
+    /// The parts of `range` not covered by the set, in order
+    pub fn gaps_in(&self, range: Range<T>) -> Vec<Range<T>> {
+        let mut gaps = Vec::new();
+        if range.is_empty() {
+            return gaps;
+        }
+
+        let Range { mut start, end } = range;
+        let mut cursor = self.ranges.lower_bound(&point(&start));
+        cursor.move_prev();
+        if cursor.current().is_none_or(|prev| prev.end <= start) {
+            cursor.move_next();
+        }
+
+        while let Some(current) = cursor.current() {
+            if current.start >= end {
+                break;
+            }
+            if current.start > start {
+                gaps.push(start..current.start.clone());
+            }
+            start = current.end.clone();
+            cursor.move_next();
+        }
+
+        if start < end {
+            gaps.push(start..end);
+        }
+
+        gaps
+    }
+
+    /// The stored ranges in order
+    pub fn iter(&self) -> rbtree::Iter<'_, Range<T>> {
+        self.ranges.iter()
+    }
+}
+
+/// Empty range used to look up the stored ranges by where they start
+fn point<T: Clone>(value: &T) -> Range<T> {
+    value.clone()..value.clone()
+}
+
+impl<'a, T: Ord + std::fmt::Debug + Clone> IntoIterator for &'a RangeSet<T> {
+    type Item = &'a Range<T>;
+    type IntoIter = rbtree::Iter<'a, Range<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> FromIterator<Range<T>> for RangeSet<T> {
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> Extend<Range<T>> for RangeSet<T> {
+    fn extend<I: IntoIterator<Item = Range<T>>>(&mut self, iter: I) {
+        for range in iter {
+            self.insert(range);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RangeSet;
+
+    fn ranges(set: &RangeSet<u32>) -> Vec<std::ops::Range<u32>> {
+        set.iter().cloned().collect()
+    }
+
+    #[test]
+    fn insert_merges_overlapping_and_adjacent() {
+        let mut set = RangeSet::new();
+        set.insert(10..20);
+        set.insert(30..40);
+        set.insert(50..60);
+        set.insert(5..5);
+        assert_eq!(ranges(&set), vec![10..20, 30..40, 50..60]);
+
+        set.insert(20..25);
+        assert_eq!(ranges(&set), vec![10..25, 30..40, 50..60]);
+
+        set.insert(35..52);
+        assert_eq!(ranges(&set), vec![10..25, 30..60]);
+
+        set.insert(0..100);
+        assert_eq!(ranges(&set), vec![0..100]);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn remove_splits_ranges() {
+        let mut set: RangeSet<u32> = [0..10, 20..30, 40..50].into_iter().collect();
+
+        set.remove(5..7);
+        assert_eq!(ranges(&set), vec![0..5, 7..10, 20..30, 40..50]);
+
+        set.remove(8..45);
+        assert_eq!(ranges(&set), vec![0..5, 7..8, 45..50]);
+
+        set.remove(0..5);
+        set.remove(50..60);
+        assert_eq!(ranges(&set), vec![7..8, 45..50]);
+
+        set.remove(0..100);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn contains_and_gaps() {
+        let set: RangeSet<u32> = [10..20, 30..40].into_iter().collect();
+
+        assert!(!set.contains(&9));
+        assert!(set.contains(&10));
+        assert!(set.contains(&19));
+        assert!(!set.contains(&20));
+        assert_eq!(set.range_containing(&35), Some(&(30..40)));
+
+        assert_eq!(set.gaps_in(0..50), vec![0..10, 20..30, 40..50]);
+        assert_eq!(set.gaps_in(15..35), vec![20..30]);
+        assert_eq!(set.gaps_in(12..18), vec![]);
+        assert_eq!(set.gaps_in(40..45), vec![40..45]);
+    }
+
+    #[test]
+    fn matches_a_bitmap() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
+        let mut set = RangeSet::new();
+        let mut bitmap = [false; 200];
+
+        for _ in 0..500 {
+            let start = rng.random_range(0..200);
+            let end = (start + rng.random_range(0..20)).min(200);
+            let adding = rng.random_bool(0.5);
+            if adding {
+                set.insert(start..end);
+            } else {
+                set.remove(start..end);
+            }
+            bitmap[start as usize..end as usize].fill(adding);
+
+            let stored = ranges(&set);
+            assert!(stored.windows(2).all(|pair| pair[0].end < pair[1].start));
+            assert!(stored.iter().all(|range| !range.is_empty()));
+            for (value, &set_bit) in bitmap.iter().enumerate() {
+                assert_eq!(set.contains(&(value as u32)), set_bit);
+            }
+        }
+
+        let gaps: u32 = set
+            .gaps_in(0..200)
+            .iter()
+            .map(|gap| gap.end - gap.start)
+            .sum();
+        assert_eq!(gaps as usize, bitmap.iter().filter(|bit| !**bit).count());
+    }
+}
@@ -1,124 +1,290 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
-pub struct RBTree<T: Ord + std::fmt::Debug + Clone> {
-    root: Link<T>,
-    nil: NonNull<NilNode<T>>,
+/// A monoid kept up to date for every subtree of an [`RBTree`]. `combine` must be associative and
+/// `empty` must be its identity, since the tree regroups the elements whenever it rotates.
+pub trait Augmentation<T> {
+    type Value: Clone;
+
+    fn empty() -> Self::Value;
+
+    fn lift(element: &T) -> Self::Value;
+
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
 }
 
-#[allow(unsafe_op_in_unsafe_fn)]
-impl<T: Ord + std::fmt::Debug + Clone> RBTree<T> {
+impl<T> Augmentation<T> for () {
+    type Value = ();
+
+    fn empty() -> Self::Value {}
+
+    fn lift(_element: &T) -> Self::Value {}
+
+    fn combine(_left: &Self::Value, _right: &Self::Value) -> Self::Value {}
+}
+
+/// Orders the elements of an [`RBTree`]
+pub trait Compare<T: ?Sized> {
+    fn compare(&self, lhs: &T, rhs: &T) -> Ordering;
+}
+
+/// Orders elements by their [`Ord`] implementation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Natural;
+
+impl<T: Ord + ?Sized> Compare<T> for Natural {
+    fn compare(&self, lhs: &T, rhs: &T) -> Ordering {
+        lhs.cmp(rhs)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Compare<T> for F {
+    fn compare(&self, lhs: &T, rhs: &T) -> Ordering {
+        self(lhs, rhs)
+    }
+}
+
+/// Orders elements by the [`Ord`] implementation of a projected key
+#[derive(Debug, Clone, Copy)]
+pub struct ByKey<F>(pub F);
+
+impl<T, K: Ord, F: Fn(&T) -> K> Compare<T> for ByKey<F> {
+    fn compare(&self, lhs: &T, rhs: &T) -> Ordering {
+        (self.0)(lhs).cmp(&(self.0)(rhs))
+    }
+}
+
+pub struct RBTree<T, A: Augmentation<T> = (), C: Compare<T> = Natural> {
+    root: Link<T, A>,
+    nil: NonNull<NilNode<T, A>>,
+    len: usize,
+    compare: C,
+    handles: HandleTable<T, A>,
+    trace: Option<Trace<T>>,
+}
+
+/// Fix-up steps recorded so far, along with how to copy the values that name their nodes. Only
+/// [`RBTree::start_trace`] needs `T: Clone` this way.
+struct Trace<T> {
+    events: Vec<RebalanceEvent<T>>,
+    clone: fn(&T) -> T,
+}
+
+impl<T: Ord> RBTree<T> {
     pub fn new() -> Self {
+        Self::new_augmented()
+    }
+}
+
+impl<T, C: Compare<T>> RBTree<T, (), C> {
+    pub fn with_comparator(compare: C) -> Self {
+        Self::with_comparator_augmented(compare)
+    }
+}
+
+impl<T, K: Ord, F: Fn(&T) -> K> RBTree<T, (), ByKey<F>> {
+    /// Orders the elements by the key `key` extracts from each of them
+    pub fn by_key(key: F) -> Self {
+        Self::with_comparator(ByKey(key))
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T> + Default> RBTree<T, A, C> {
+    pub fn new_augmented() -> Self {
+        Self::with_comparator_augmented(C::default())
+    }
+
+    /// Builds the tree in O(n) from values already sorted by the comparator. Panics otherwise.
+    pub fn from_sorted_iter(values: impl IntoIterator<Item = T>) -> Self {
+        let mut tree = Self::new_augmented();
+        tree.build_sorted(values.into_iter().collect());
+        tree
+    }
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<T, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    pub fn with_comparator_augmented(compare: C) -> Self {
         let nil = NilNode::new();
         Self {
-            root: Link::Nil(nil),
+            root: Link::Nil,
             nil,
+            len: 0,
+            compare,
+            handles: HandleTable::new(),
+            trace: None,
         }
     }
 
-    pub unsafe fn unsafe_search(&self, element: &T) -> Option<NonNull<Node<T>>> {
+    pub fn comparator(&self) -> &C {
+        &self.compare
+    }
+
+    /// Hands over the steps recorded so far and keeps recording
+    pub fn take_trace(&mut self) -> Vec<RebalanceEvent<T>> {
+        self.trace
+            .as_mut()
+            .map(|trace| std::mem::take(&mut trace.events))
+            .unwrap_or_default()
+    }
+
+    /// Hands over the steps recorded so far and stops recording
+    pub fn stop_trace(&mut self) -> Vec<RebalanceEvent<T>> {
+        self.trace
+            .take()
+            .map(|trace| trace.events)
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) unsafe fn unsafe_search(&self, element: &T) -> Option<NonNull<Node<T, A>>> {
+        self.search_by(|value| self.compare.compare(element, value))
+    }
+
+    /// `compare` orders the target relative to the value of the node being visited
+    pub(crate) unsafe fn search_by(
+        &self,
+        mut compare: impl FnMut(&T) -> Ordering,
+    ) -> Option<NonNull<Node<T, A>>> {
         let mut traverse_node = self.root;
-        while let Link::Real(node) = traverse_node
-            && &(*node.as_ptr()).value != element
-        {
-            if element > &(*node.as_ptr()).value {
-                traverse_node = (*node.as_ptr()).right;
-            } else {
-                traverse_node = (*node.as_ptr()).left;
+        while let Link::Real(node) = traverse_node {
+            match compare(&(*node.as_ptr()).value) {
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => traverse_node = (*node.as_ptr()).right,
+                Ordering::Less => traverse_node = (*node.as_ptr()).left,
             }
         }
 
-        if let Link::Real(node) = traverse_node {
-            Some(node)
-        } else {
-            None
-        }
+        None
     }
 
-    pub fn search(&mut self, element: &T) -> bool {
+    pub fn search(&self, element: &T) -> bool {
         unsafe { self.unsafe_search(&element).is_some() }
     }
 
-    pub unsafe fn unsafe_insert(&mut self, element: T) -> NonNull<Node<T>> {
+    pub(crate) unsafe fn unsafe_insert(&mut self, element: T) -> NonNull<Node<T, A>> {
         let new_node = Node::new(element, self.nil(), self.nil());
         let mut traverse_target = self.root;
         let mut traverse_parent = self.nil();
+        let mut goes_left = false;
 
         while let Link::Real(target) = traverse_target {
             traverse_parent = traverse_target;
-            if (*new_node.as_ptr()).value < (*target.as_ptr()).value {
+            goes_left = self
+                .compare
+                .compare(&(*new_node.as_ptr()).value, &(*target.as_ptr()).value)
+                == Ordering::Less;
+            if goes_left {
                 traverse_target = (*target.as_ptr()).left;
             } else {
                 traverse_target = (*target.as_ptr()).right;
             }
         }
 
-        (*new_node.as_ptr()).parent = traverse_parent;
+        self.attach(new_node, traverse_parent, goes_left);
 
-        if let Link::Nil(_) = traverse_parent {
-            self.root = Link::Real(new_node)
-        } else if let Link::Real(parent) = traverse_parent {
-            if (*new_node.as_ptr()).value < (*parent.as_ptr()).value {
-                (*parent.as_ptr()).left = Link::Real(new_node);
-            } else {
-                (*parent.as_ptr()).right = Link::Real(new_node);
-            }
+        new_node
+    }
+
+    /// Links a new node in as a child of `parent` on the given side, which must be nil, or as
+    /// the root when `parent` is nil
+    unsafe fn attach(&mut self, node: NonNull<Node<T, A>>, parent: Link<T, A>, as_left: bool) {
+        (*node.as_ptr()).parent = parent;
+
+        match parent {
+            Link::Real(parent) if as_left => (*parent.as_ptr()).left = Link::Real(node),
+            Link::Real(parent) => (*parent.as_ptr()).right = Link::Real(node),
+            Link::Nil => self.root = Link::Real(node),
         }
 
-        let mut rule_violator = new_node;
+        Node::update_to_root(node);
+        self.insert_fix(node);
+
+        self.len += 1;
+    }
+
+    /// Restores the colouring after `node` was linked in red. Returns whether the root had to be
+    /// blackened, which raises the black height of the tree by one.
+    unsafe fn insert_fix(&mut self, node: NonNull<Node<T, A>>) -> bool {
+        let mut rule_violator = node;
 
         while Link::Real(rule_violator) != self.root
             && (*rule_violator.as_ptr()).parent.color() == Color::Red
         {
             let mut parent = (*rule_violator.as_ptr()).parent.into_node();
             let mut grandparent = (*parent.as_ptr()).parent.into_node();
-            if Link::Real(parent) == (*grandparent.as_ptr()).left {
-                let uncle = (*grandparent.as_ptr()).right;
-                if uncle.color() == Color::Red {
-                    (*parent.as_ptr()).color = Color::Black;
-                    (*uncle.into_node().as_ptr()).color = Color::Black;
-                    (*grandparent.as_ptr()).color = Color::Red;
-                    rule_violator = grandparent;
-                } else {
-                    if Link::Real(rule_violator) == (*parent.as_ptr()).right {
-                        rule_violator = parent;
-                        self.rotate_left(rule_violator);
-                    }
+            let mirrored = Link::Real(parent) != (*grandparent.as_ptr()).left;
+            let uncle = if mirrored {
+                (*grandparent.as_ptr()).left
+            } else {
+                (*grandparent.as_ptr()).right
+            };
 
-                    parent = (*rule_violator.as_ptr()).parent.into_node();
-                    grandparent = (*parent.as_ptr()).parent.into_node();
+            if uncle.color() == Color::Red {
+                self.record_node(rule_violator, |node| RebalanceEvent::InsertCase {
+                    case: 1,
+                    node,
+                    mirrored,
+                });
+                self.recolor(parent, Color::Black);
+                self.recolor(uncle.into_node(), Color::Black);
+                self.recolor(grandparent, Color::Red);
+                rule_violator = grandparent;
+                continue;
+            }
 
-                    (*parent.as_ptr()).color = Color::Black;
-                    (*grandparent.as_ptr()).color = Color::Red;
-                    self.rotate_right(grandparent);
-                }
+            let inner_child = if mirrored {
+                (*parent.as_ptr()).left
             } else {
-                let uncle = (*grandparent.as_ptr()).left;
-                if uncle.color() == Color::Red {
-                    (*parent.as_ptr()).color = Color::Black;
-                    (*uncle.into_node().as_ptr()).color = Color::Black;
-                    (*grandparent.as_ptr()).color = Color::Red;
-                    rule_violator = grandparent;
+                (*parent.as_ptr()).right
+            };
+            if Link::Real(rule_violator) == inner_child {
+                self.record_node(rule_violator, |node| RebalanceEvent::InsertCase {
+                    case: 2,
+                    node,
+                    mirrored,
+                });
+                rule_violator = parent;
+                if mirrored {
+                    self.rotate_right(rule_violator);
                 } else {
-                    if Link::Real(rule_violator) == (*parent.as_ptr()).left {
-                        rule_violator = parent;
-                        self.rotate_right(rule_violator);
-                    }
-
-                    parent = (*rule_violator.as_ptr()).parent.into_node();
-                    grandparent = (*parent.as_ptr()).parent.into_node();
-
-                    (*parent.as_ptr()).color = Color::Black;
-                    (*grandparent.as_ptr()).color = Color::Red;
-                    self.rotate_left(grandparent);
+                    self.rotate_left(rule_violator);
                 }
             }
-        }
 
-        if let Link::Real(root) = self.root {
-            (*root.as_ptr()).color = Color::Black;
+            parent = (*rule_violator.as_ptr()).parent.into_node();
+            grandparent = (*parent.as_ptr()).parent.into_node();
+
+            self.record_node(rule_violator, |node| RebalanceEvent::InsertCase {
+                case: 3,
+                node,
+                mirrored,
+            });
+            self.recolor(parent, Color::Black);
+            self.recolor(grandparent, Color::Red);
+            if mirrored {
+                self.rotate_left(grandparent);
+            } else {
+                self.rotate_right(grandparent);
+            }
         }
 
-        new_node
+        let root = self.root.into_node();
+        let grew = (*root.as_ptr()).color == Color::Red;
+        self.recolor(root, Color::Black);
+
+        grew
     }
 
     pub fn insert(&mut self, element: T) {
@@ -127,159 +293,259 @@ impl<T: Ord + std::fmt::Debug + Clone> RBTree<T> {
         }
     }
 
+    /// Like [`RBTree::insert`], also returning a handle to the new element that stays valid until
+    /// it is removed. Only elements handed out this way are tracked by the handle table.
+    pub fn insert_with_handle(&mut self, element: T) -> Handle {
+        unsafe {
+            let node = self.unsafe_insert(element);
+            self.handles.handle_of(node)
+        }
+    }
+
+    /// O(1)
+    pub fn get_by_handle(&self, handle: Handle) -> Result<&T, HandleError> {
+        let node = self.handles.resolve(handle)?;
+        unsafe { Ok(&(*node.as_ptr()).value) }
+    }
+
+    /// Removes the element without searching for it, so only the rebalancing walk is paid
+    pub fn remove_by_handle(&mut self, handle: Handle) -> Result<T, HandleError> {
+        let node = self.handles.resolve(handle)?;
+        unsafe { Ok(self.remove_node(node)) }
+    }
+
+    /// The elements just before and after the one behind `handle`
+    pub fn neighbors(&self, handle: Handle) -> Result<(Option<&T>, Option<&T>), HandleError> {
+        let node = self.handles.resolve(handle)?;
+        let value =
+            |node: Option<NonNull<Node<T, A>>>| node.map(|node| unsafe { &(*node.as_ptr()).value });
+        Ok((value(Node::prev_node(node)), value(Node::next_node(node))))
+    }
+
     pub fn delete(&mut self, element: &T) -> bool {
         unsafe {
-            let mut traversed_node = self.root;
-            while let Link::Real(node) = traversed_node
-                && &(*node.as_ptr()).value != element
-            {
-                if element > &(*node.as_ptr()).value {
-                    traversed_node = (*node.as_ptr()).right;
-                } else {
-                    traversed_node = (*node.as_ptr()).left;
+            match self.unsafe_search(element) {
+                Some(node) => {
+                    self.remove_node(node);
+                    true
                 }
+                None => false,
             }
+        }
+    }
 
-            if traversed_node.is_nil() {
-                return false;
-            }
+    /// Removes the node found by `compare`, see [`RBTree::search_by`]
+    pub(crate) fn remove_by(&mut self, compare: impl FnMut(&T) -> Ordering) -> Option<T> {
+        unsafe {
+            let node = self.search_by(compare)?;
+            Some(self.remove_node(node))
+        }
+    }
 
-            let deletion_target = traversed_node.into_node();
-            let spliced_node = if (*deletion_target.as_ptr()).left.is_nil()
-                || (*deletion_target.as_ptr()).right.is_nil()
-            {
-                deletion_target
-            } else {
-                Node::in_order_successor(deletion_target)
-            };
+    pub fn first(&self) -> Option<&T> {
+        unsafe { self.first_node().map(|node| &(*node.as_ptr()).value) }
+    }
 
-            let child_of_spliced_node =
-                if let Link::Real(left_child) = (*spliced_node.as_ptr()).left {
-                    Link::Real(left_child)
-                } else {
-                    (*spliced_node.as_ptr()).right
-                };
+    pub fn last(&self) -> Option<&T> {
+        unsafe { self.last_node().map(|node| &(*node.as_ptr()).value) }
+    }
 
-            match child_of_spliced_node {
-                Link::Real(real) => (*real.as_ptr()).parent = (*spliced_node.as_ptr()).parent,
-                Link::Nil(nil) => {
-                    (*nil.as_ptr()).parent =
-                        if let Link::Real(parent) = (*spliced_node.as_ptr()).parent {
-                            Some(parent)
-                        } else {
-                            None
-                        }
-                }
-            }
+    pub fn pop_first(&mut self) -> Option<T> {
+        unsafe {
+            let node = self.first_node()?;
+            Some(self.remove_node(node))
+        }
+    }
 
-            if let Link::Real(parent) = (*spliced_node.as_ptr()).parent {
-                if (*parent.as_ptr()).left == Link::Real(spliced_node) {
-                    (*parent.as_ptr()).left = child_of_spliced_node;
-                } else {
-                    (*parent.as_ptr()).right = child_of_spliced_node;
-                }
-            } else {
-                self.root = child_of_spliced_node;
-            }
+    pub fn pop_last(&mut self) -> Option<T> {
+        unsafe {
+            let node = self.last_node()?;
+            Some(self.remove_node(node))
+        }
+    }
+
+    /// `deletion_target` must be a node of this tree
+    pub(crate) unsafe fn remove_node(&mut self, deletion_target: NonNull<Node<T, A>>) -> T {
+        let spliced_node = if (*deletion_target.as_ptr()).left.is_nil()
+            || (*deletion_target.as_ptr()).right.is_nil()
+        {
+            deletion_target
+        } else {
+            Node::in_order_successor(deletion_target)
+        };
 
-            if spliced_node != deletion_target {
-                (*deletion_target.as_ptr()).value = (*spliced_node.as_ptr()).value.clone();
+        let child_of_spliced_node = if let Link::Real(left_child) = (*spliced_node.as_ptr()).left {
+            Link::Real(left_child)
+        } else {
+            (*spliced_node.as_ptr()).right
+        };
+
+        match child_of_spliced_node {
+            Link::Real(real) => (*real.as_ptr()).parent = (*spliced_node.as_ptr()).parent,
+            Link::Nil => {
+                (*self.nil.as_ptr()).parent =
+                    if let Link::Real(parent) = (*spliced_node.as_ptr()).parent {
+                        Some(parent)
+                    } else {
+                        None
+                    }
             }
+        }
 
-            if (*spliced_node.as_ptr()).color == Color::Black {
-                self.delete_fix(child_of_spliced_node);
+        if let Link::Real(parent) = (*spliced_node.as_ptr()).parent {
+            if (*parent.as_ptr()).left == Link::Real(spliced_node) {
+                (*parent.as_ptr()).left = child_of_spliced_node;
+            } else {
+                (*parent.as_ptr()).right = child_of_spliced_node;
             }
+        } else {
+            self.root = child_of_spliced_node;
+        }
+
+        if spliced_node != deletion_target {
+            std::mem::swap(
+                &mut (*deletion_target.as_ptr()).value,
+                &mut (*spliced_node.as_ptr()).value,
+            );
+            std::mem::swap(
+                &mut (*deletion_target.as_ptr()).slot,
+                &mut (*spliced_node.as_ptr()).slot,
+            );
+            self.handles.relink(deletion_target);
+        }
+        self.handles.release(spliced_node);
 
-            let _ = Box::from_raw(spliced_node.as_ptr());
+        if let Link::Real(parent) = (*spliced_node.as_ptr()).parent {
+            Node::update_to_root(parent);
+        }
 
-            true
+        if (*spliced_node.as_ptr()).color == Color::Black {
+            self.delete_fix(child_of_spliced_node);
         }
+        (*self.nil.as_ptr()).parent = None;
+
+        self.len -= 1;
+
+        Box::from_raw(spliced_node.as_ptr()).value
     }
 
-    fn delete_fix(&mut self, node: Link<T>) {
+    fn delete_fix(&mut self, node: Link<T, A>) {
         unsafe {
             let mut double_black = node;
             while double_black != self.root && double_black.color() == Color::Black {
-                let parent = double_black.parent().into_node();
-                if double_black == (*parent.as_ptr()).left {
-                    let mut sibling_of_double_black = (*parent.as_ptr()).right.into_node();
-                    if (*sibling_of_double_black.as_ptr()).color == Color::Red {
-                        (*sibling_of_double_black.as_ptr()).color = Color::Black;
-                        (*parent.as_ptr()).color = Color::Red;
-                        self.rotate_left(parent);
-
-                        sibling_of_double_black = (*parent.as_ptr()).right.into_node();
+                let parent = self.parent_of(double_black).into_node();
+                let mirrored = double_black != (*parent.as_ptr()).left;
+                let sibling_of = |parent: NonNull<Node<T, A>>| {
+                    if mirrored {
+                        (*parent.as_ptr()).left.into_node()
+                    } else {
+                        (*parent.as_ptr()).right.into_node()
                     }
-                    if (*sibling_of_double_black.as_ptr()).left.color() == Color::Black
-                        && (*sibling_of_double_black.as_ptr()).right.color() == Color::Black
-                    {
-                        (*sibling_of_double_black.as_ptr()).color = Color::Red;
-                        double_black = double_black.parent();
+                };
+                // Children of the sibling nearer to and further from the double black node
+                let near_and_far = |sibling: NonNull<Node<T, A>>| {
+                    let (left, right) = ((*sibling.as_ptr()).left, (*sibling.as_ptr()).right);
+                    if mirrored {
+                        (right, left)
                     } else {
-                        if (*sibling_of_double_black.as_ptr()).right.color() == Color::Black {
-                            if let Link::Real(left) = (*sibling_of_double_black.as_ptr()).left {
-                                (*left.as_ptr()).color = Color::Black;
-                            }
-                            (*sibling_of_double_black.as_ptr()).color = Color::Red;
-                            self.rotate_right(sibling_of_double_black);
-                            sibling_of_double_black = (*parent.as_ptr()).right.into_node();
-                        }
-
-                        (*sibling_of_double_black.as_ptr()).color = (*parent.as_ptr()).color;
-                        (*parent.as_ptr()).color = Color::Black;
-                        (*(*sibling_of_double_black.as_ptr())
-                            .right
-                            .into_node()
-                            .as_ptr())
-                        .color = Color::Black;
-                        self.rotate_left(parent);
-                        double_black = self.root;
+                        (left, right)
                     }
-                } else {
-                    let mut sibling_of_double_black = (*parent.as_ptr()).left.into_node();
-                    if (*sibling_of_double_black.as_ptr()).color == Color::Red {
-                        (*sibling_of_double_black.as_ptr()).color = Color::Black;
-                        (*parent.as_ptr()).color = Color::Red;
-                        self.rotate_right(parent);
+                };
 
-                        sibling_of_double_black = (*parent.as_ptr()).left.into_node();
+                let mut sibling_of_double_black = sibling_of(parent);
+                if (*sibling_of_double_black.as_ptr()).color == Color::Red {
+                    self.record_node(parent, |node| RebalanceEvent::DeleteCase {
+                        case: 1,
+                        node,
+                        mirrored,
+                    });
+                    self.recolor(sibling_of_double_black, Color::Black);
+                    self.recolor(parent, Color::Red);
+                    if mirrored {
+                        self.rotate_right(parent);
+                    } else {
+                        self.rotate_left(parent);
                     }
-                    if (*sibling_of_double_black.as_ptr()).right.color() == Color::Black
-                        && (*sibling_of_double_black.as_ptr()).left.color() == Color::Black
-                    {
-                        (*sibling_of_double_black.as_ptr()).color = Color::Red;
-                        double_black = double_black.parent();
+
+                    sibling_of_double_black = sibling_of(parent);
+                }
+
+                let (near, far) = near_and_far(sibling_of_double_black);
+                if near.color() == Color::Black && far.color() == Color::Black {
+                    self.record_node(parent, |node| RebalanceEvent::DeleteCase {
+                        case: 2,
+                        node,
+                        mirrored,
+                    });
+                    self.recolor(sibling_of_double_black, Color::Red);
+                    double_black = self.parent_of(double_black);
+                    continue;
+                }
+
+                if far.color() == Color::Black {
+                    self.record_node(parent, |node| RebalanceEvent::DeleteCase {
+                        case: 3,
+                        node,
+                        mirrored,
+                    });
+                    self.recolor(near.into_node(), Color::Black);
+                    self.recolor(sibling_of_double_black, Color::Red);
+                    if mirrored {
+                        self.rotate_left(sibling_of_double_black);
                     } else {
-                        if (*sibling_of_double_black.as_ptr()).left.color() == Color::Black {
-                            if let Link::Real(right) = (*sibling_of_double_black.as_ptr()).right {
-                                (*right.as_ptr()).color = Color::Black;
-                            }
-                            (*sibling_of_double_black.as_ptr()).color = Color::Red;
-                            self.rotate_left(sibling_of_double_black);
-                            sibling_of_double_black = (*parent.as_ptr()).left.into_node();
-                        }
-
-                        (*sibling_of_double_black.as_ptr()).color = (*parent.as_ptr()).color;
-                        (*parent.as_ptr()).color = Color::Black;
-                        (*(*sibling_of_double_black.as_ptr())
-                            .left
-                            .into_node()
-                            .as_ptr())
-                        .color = Color::Black;
-                        self.rotate_right(parent);
-                        double_black = self.root;
+                        self.rotate_right(sibling_of_double_black);
                     }
+                    sibling_of_double_black = sibling_of(parent);
+                }
+
+                self.record_node(parent, |node| RebalanceEvent::DeleteCase {
+                    case: 4,
+                    node,
+                    mirrored,
+                });
+                self.recolor(sibling_of_double_black, (*parent.as_ptr()).color);
+                self.recolor(parent, Color::Black);
+                self.recolor(
+                    near_and_far(sibling_of_double_black).1.into_node(),
+                    Color::Black,
+                );
+                if mirrored {
+                    self.rotate_right(parent);
+                } else {
+                    self.rotate_left(parent);
                 }
+                double_black = self.root;
             }
 
             if let Link::Real(node) = double_black {
-                (*node.as_ptr()).color = Color::Black;
+                self.recolor(node, Color::Black);
             }
         }
     }
 
-    fn rotate_left(&mut self, node: NonNull<Node<T>>) {
+    /// Sets the colour of `node`, tracing the change if there is one
+    unsafe fn recolor(&mut self, node: NonNull<Node<T, A>>, color: Color) {
+        if (*node.as_ptr()).color != color {
+            (*node.as_ptr()).color = color;
+            self.record_node(node, |node| RebalanceEvent::Recolor { node, color });
+        }
+    }
+
+    /// Traces an event about `node`, cloning its value only while a trace is being recorded
+    unsafe fn record_node(
+        &mut self,
+        node: NonNull<Node<T, A>>,
+        event: impl FnOnce(T) -> RebalanceEvent<T>,
+    ) {
+        if let Some(trace) = &mut self.trace {
+            let value = (trace.clone)(&(*node.as_ptr()).value);
+            trace.events.push(event(value));
+        }
+    }
+
+    fn rotate_left(&mut self, node: NonNull<Node<T, A>>) {
         unsafe {
+            self.record_node(node, |node| RebalanceEvent::RotateLeft { node });
             let new_left = node;
             let new_top = (*node.as_ptr()).right.into_node();
             (*new_left.as_ptr()).right = (*new_top.as_ptr()).left;
@@ -298,11 +564,15 @@ impl<T: Ord + std::fmt::Debug + Clone> RBTree<T> {
             }
             (*new_top.as_ptr()).left = Link::Real(new_left);
             (*new_left.as_ptr()).parent = Link::Real(new_top);
+
+            Node::update(new_left);
+            Node::update(new_top);
         }
     }
 
-    fn rotate_right(&mut self, node: NonNull<Node<T>>) {
+    fn rotate_right(&mut self, node: NonNull<Node<T, A>>) {
         unsafe {
+            self.record_node(node, |node| RebalanceEvent::RotateRight { node });
             let new_right = node;
             let new_top = (*node.as_ptr()).left.into_node();
             (*new_right.as_ptr()).left = (*new_top.as_ptr()).right;
@@ -321,1014 +591,4124 @@ impl<T: Ord + std::fmt::Debug + Clone> RBTree<T> {
             }
             (*new_top.as_ptr()).right = Link::Real(new_right);
             (*new_right.as_ptr()).parent = Link::Real(new_top);
+
+            Node::update(new_right);
+            Node::update(new_top);
         }
     }
 
-    fn nil(&self) -> Link<T> {
-        Link::Nil(self.nil)
+    fn nil(&self) -> Link<T, A> {
+        Link::Nil
     }
 
-    pub fn in_order_vec(&self) -> Vec<T> {
-        if let Link::Real(root) = self.root {
-            unsafe { root.as_ref().in_order_vec() }
-        } else {
-            Vec::new()
+    /// A nil link only has a parent while a deletion is being fixed up, and it is kept in the
+    /// shared `NilNode`
+    fn parent_of(&self, link: Link<T, A>) -> Link<T, A> {
+        unsafe {
+            match link {
+                Link::Real(real) => (*real.as_ptr()).parent,
+                Link::Nil => match (*self.nil.as_ptr()).parent {
+                    Some(parent) => Link::Real(parent),
+                    None => Link::Nil,
+                },
+            }
         }
     }
 
-    pub fn print(&self) {
+    pub fn in_order_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.iter().cloned().collect()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, A> {
         unsafe {
-            self.root.into_node().as_ref().print("", true, "");
+            Iter {
+                front: self.first_node(),
+                back: self.last_node(),
+                remaining: self.len,
+                _marker: PhantomData,
+            }
         }
     }
-}
 
-impl<'a, T: Ord + std::fmt::Debug + Clone + 'a> RBTree<T>
-where
-    &'a T: std::ops::Sub<&'a T, Output = T>,
-{
-    pub fn get_nearest(&self, element: &'a T) -> Option<&T> {
+    /// Closest element to `target` under `distance`, preferring the smaller one on ties. Only the
+    /// floor and the ceiling of `target` can be closest, so both are checked.
+    pub fn nearest_by<D: PartialOrd>(
+        &self,
+        target: &T,
+        distance: impl Fn(&T, &T) -> D,
+    ) -> Option<&T> {
+        match (self.floor(target), self.ceiling(target)) {
+            (Some(below), Some(above)) => {
+                if distance(above, target) < distance(below, target) {
+                    Some(above)
+                } else {
+                    Some(below)
+                }
+            }
+            (below, above) => below.or(above),
+        }
+    }
+
+    /// The `k` closest elements to `target` under `distance`, closest first. Walks outwards from
+    /// the floor of `target` in both directions, so this takes O(log n + k).
+    pub fn k_nearest_by<D: PartialOrd>(
+        &self,
+        target: &T,
+        k: usize,
+        distance: impl Fn(&T, &T) -> D,
+    ) -> Vec<&T> {
         unsafe {
-            let mut traverse_node = self.root;
-            while let Link::Real(node) = traverse_node
-                && &(*node.as_ptr()).value != element
-            {
-                let traverse_value = &(*traverse_node.into_node().as_ptr()).value;
-                // let element = element.clone();
-                /// Return the closest element to the target, even if it is not exactly the target
-                if let Link::Real(left) = (*node.as_ptr()).left
-                    && let Link::Real(right) = (*node.as_ptr()).right
-                    && &(*left.as_ptr()).value < element
-                    && &(*right.as_ptr()).value > element
-                {
-                    if traverse_value < element
-                        && (&(*right.as_ptr()).value - element) < (element - traverse_value)
-                    {
-                        traverse_node = (*node.as_ptr()).right;
-                    } else if traverse_value < element
-                        && ((element - &(*left.as_ptr()).value) < (traverse_value - element))
-                    {
-                        traverse_node = (*node.as_ptr()).left;
-                    }
-                    break;
-                } else if (*node.as_ptr()).left.is_nil()
-                    && let Link::Real(right) = (*node.as_ptr()).right
-                    && &(*right.as_ptr()).value > element
-                {
-                    if traverse_value < element
-                        && ((&(*right.as_ptr()).value - element) < (element - traverse_value))
-                    {
-                        traverse_node = (*node.as_ptr()).right;
-                    }
-                    break;
-                } else if (*node.as_ptr()).right.is_nil()
-                    && let Link::Real(left) = (*node.as_ptr()).left
-                    && &(*left.as_ptr()).value < element
-                {
-                    if traverse_value < element
-                        && ((element - &(*left.as_ptr()).value) < (traverse_value - element))
-                    {
-                        traverse_node = (*node.as_ptr()).left;
+            let mut below = self
+                .partition_last(|value| self.compare.compare(value, target) == Ordering::Greater);
+            let mut above = match below {
+                Some(below) => Node::next_node(below),
+                None => self.first_node(),
+            };
+
+            let mut nearest = Vec::with_capacity(k.min(self.len));
+            while nearest.len() < k {
+                let take_above = match (below, above) {
+                    (Some(below), Some(above)) => {
+                        distance(&(*above.as_ptr()).value, target)
+                            < distance(&(*below.as_ptr()).value, target)
                     }
-                    break;
-                } else if (*node.as_ptr()).left.is_nil() && (*node.as_ptr()).right.is_nil() {
-                    break;
-                }
+                    (Some(_), None) => false,
+                    (None, Some(_)) => true,
+                    (None, None) => break,
+                };
 
-                if element > &(*node.as_ptr()).value {
-                    traverse_node = (*node.as_ptr()).right;
+                if take_above {
+                    let node = above.unwrap();
+                    nearest.push(&(*node.as_ptr()).value);
+                    above = Node::next_node(node);
                 } else {
-                    traverse_node = (*node.as_ptr()).left;
+                    let node = below.unwrap();
+                    nearest.push(&(*node.as_ptr()).value);
+                    below = Node::prev_node(node);
                 }
             }
 
-            if let Link::Real(node) = traverse_node {
-                Some(&(*node.as_ptr()).value)
-            } else {
-                None
+            nearest
+        }
+    }
+
+    /// The `index`-th smallest element, counting from zero
+    pub fn select(&self, index: usize) -> Option<&T> {
+        unsafe { self.select_node(index).map(|node| &(*node.as_ptr()).value) }
+    }
+
+    pub(crate) unsafe fn select_node(&self, mut index: usize) -> Option<NonNull<Node<T, A>>> {
+        let mut traverse_node = self.root;
+        while let Link::Real(node) = traverse_node {
+            let left_size = (*node.as_ptr()).left.size();
+            match index.cmp(&left_size) {
+                Ordering::Less => traverse_node = (*node.as_ptr()).left,
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => {
+                    index -= left_size + 1;
+                    traverse_node = (*node.as_ptr()).right;
+                }
             }
         }
+
+        None
     }
-}
 
-impl<T: Ord + std::fmt::Debug + Clone> Drop for RBTree<T> {
-    fn drop(&mut self) {
-        if let Link::Real(root) = self.root {
-            Node::node_drop(root);
+    /// Links `element` in at position `index` without consulting the comparator, which the
+    /// caller has to keep consistent with. `index` must be at most `len`.
+    pub(crate) fn insert_at(&mut self, mut index: usize, element: T) {
+        unsafe {
+            let new_node = Node::new(element, self.nil(), self.nil());
+            let mut traverse_target = self.root;
+            let mut traverse_parent = self.nil();
+            let mut goes_left = false;
+
+            while let Link::Real(target) = traverse_target {
+                traverse_parent = traverse_target;
+                let left_size = (*target.as_ptr()).left.size();
+                goes_left = index <= left_size;
+                if goes_left {
+                    traverse_target = (*target.as_ptr()).left;
+                } else {
+                    index -= left_size + 1;
+                    traverse_target = (*target.as_ptr()).right;
+                }
+            }
+
+            self.attach(new_node, traverse_parent, goes_left);
         }
+    }
 
+    /// Number of elements strictly less than `element`
+    pub fn rank(&self, element: &T) -> usize {
         unsafe {
-            let _ = Box::from_raw(self.nil.as_ptr());
+            let mut rank = 0;
+            let mut traverse_node = self.root;
+            while let Link::Real(node) = traverse_node {
+                if self.compare.compare(element, &(*node.as_ptr()).value) != Ordering::Greater {
+                    traverse_node = (*node.as_ptr()).left;
+                } else {
+                    rank += (*node.as_ptr()).left.size() + 1;
+                    traverse_node = (*node.as_ptr()).right;
+                }
+            }
+
+            rank
         }
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum Link<T: Ord + std::fmt::Debug> {
-    Real(NonNull<Node<T>>),
-    Nil(NonNull<NilNode<T>>),
-}
+    /// Aggregate over every element of the tree
+    pub fn aggregate(&self) -> A::Value {
+        self.root.with_aggregate(Clone::clone)
+    }
 
-impl<T: Ord + std::fmt::Debug> Copy for Link<T> {}
+    /// Aggregate over the elements inside `range`, combined in order
+    pub fn range_aggregate<R: RangeBounds<T>>(&self, range: R) -> A::Value {
+        Self::aggregate_where(
+            self.root,
+            &mut |value| self.before_start(&range, value),
+            &mut |value| self.after_end(&range, value),
+            true,
+            true,
+        )
+    }
 
-impl<T: Ord + std::fmt::Debug> Clone for Link<T> {
-    fn clone(&self) -> Self {
-        match self {
-            Link::Real(ptr) => Link::Real(*ptr),
-            Link::Nil(ptr) => Link::Nil(*ptr),
+    fn before_start<R: RangeBounds<T>>(&self, range: &R, value: &T) -> bool {
+        match range.start_bound() {
+            Bound::Included(start) => self.compare.compare(value, start) == Ordering::Less,
+            Bound::Excluded(start) => self.compare.compare(value, start) != Ordering::Greater,
+            Bound::Unbounded => false,
         }
     }
-}
 
-impl<T: Ord + std::fmt::Debug> Link<T> {
-    fn color(&self) -> Color {
+    fn after_end<R: RangeBounds<T>>(&self, range: &R, value: &T) -> bool {
+        match range.end_bound() {
+            Bound::Included(end) => self.compare.compare(value, end) == Ordering::Greater,
+            Bound::Excluded(end) => self.compare.compare(value, end) != Ordering::Less,
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Only one side of the tree is ever left unresolved at each level, so this visits O(log n)
+    /// nodes. `check_start` and `check_end` are cleared once a subtree is known to lie inside
+    /// that bound.
+    fn aggregate_where(
+        link: Link<T, A>,
+        before_start: &mut impl FnMut(&T) -> bool,
+        after_end: &mut impl FnMut(&T) -> bool,
+        check_start: bool,
+        check_end: bool,
+    ) -> A::Value {
         unsafe {
-            match self {
-                Link::Real(real) => (*real.as_ptr()).color,
-                Link::Nil(nil) => (*nil.as_ptr()).color,
+            let Link::Real(node) = link else {
+                return A::empty();
+            };
+            let node = &*node.as_ptr();
+
+            if !check_start && !check_end {
+                node.aggregate.clone()
+            } else if check_start && before_start(&node.value) {
+                Self::aggregate_where(node.right, before_start, after_end, check_start, check_end)
+            } else if check_end && after_end(&node.value) {
+                Self::aggregate_where(node.left, before_start, after_end, check_start, check_end)
+            } else {
+                let left =
+                    Self::aggregate_where(node.left, before_start, after_end, check_start, false);
+                let right =
+                    Self::aggregate_where(node.right, before_start, after_end, false, check_end);
+                A::combine(&A::combine(&left, &A::lift(&node.value)), &right)
             }
         }
     }
 
-    fn into_node(self) -> NonNull<Node<T>> {
+    /// Lazily yields the elements inside `range` in order
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, A> {
+        self.range_by(
+            |value| self.before_start(&range, value),
+            |value| self.after_end(&range, value),
+        )
+    }
+
+    /// Both predicates must be monotone over the in-order sequence, see [`RBTree::partition_first`]
+    /// and [`RBTree::partition_last`]
+    pub(crate) fn range_by(
+        &self,
+        before_start: impl FnMut(&T) -> bool,
+        mut after_end: impl FnMut(&T) -> bool,
+    ) -> Range<'_, T, A> {
         unsafe {
-            let Link::Real(node) = self else {
-                panic!("Unwrapped node is nil")
+            let front = self.partition_first(before_start);
+            let is_empty = match front {
+                Some(front) => after_end(&(*front.as_ptr()).value),
+                None => true,
             };
 
-            node
+            if is_empty {
+                Range {
+                    front: None,
+                    back: None,
+                    _marker: PhantomData,
+                }
+            } else {
+                Range {
+                    front,
+                    back: self.partition_last(after_end),
+                    _marker: PhantomData,
+                }
+            }
         }
     }
 
-    fn is_nil(&self) -> bool {
-        match self {
-            Link::Real(_) => false,
-            Link::Nil(_) => true,
+    /// Largest element less than or equal to `element`
+    pub fn floor(&self, element: &T) -> Option<&T> {
+        unsafe {
+            self.partition_last(|value| self.compare.compare(value, element) == Ordering::Greater)
+                .map(|node| &(*node.as_ptr()).value)
         }
     }
 
-    fn parent(&self) -> Link<T> {
+    /// Smallest element greater than or equal to `element`
+    pub fn ceiling(&self, element: &T) -> Option<&T> {
         unsafe {
-            match self {
-                Link::Real(real) => (*real.as_ptr()).parent,
-                Link::Nil(nil) => match (*nil.as_ptr()).parent {
-                    Some(parent) => Link::Real(parent),
-                    None => Link::Nil(*nil),
-                },
-            }
+            self.partition_first(|value| self.compare.compare(value, element) == Ordering::Less)
+                .map(|node| &(*node.as_ptr()).value)
         }
     }
-}
 
-#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
-enum Color {
-    Red,
-    Black,
-}
+    /// Largest element strictly less than `element`
+    pub fn predecessor(&self, element: &T) -> Option<&T> {
+        unsafe {
+            self.partition_last(|value| self.compare.compare(value, element) != Ordering::Less)
+                .map(|node| &(*node.as_ptr()).value)
+        }
+    }
 
-#[derive(Debug)]
-pub struct Node<T: Ord + std::fmt::Debug> {
-    pub value: T,
-    color: Color,
-    left: Link<T>,
-    right: Link<T>,
-    parent: Link<T>,
-}
+    /// Smallest element strictly greater than `element`
+    pub fn successor(&self, element: &T) -> Option<&T> {
+        unsafe {
+            self.partition_first(|value| self.compare.compare(value, element) != Ordering::Greater)
+                .map(|node| &(*node.as_ptr()).value)
+        }
+    }
 
-pub struct NilNode<T: Ord + std::fmt::Debug> {
-    color: Color,
-    parent: Option<NonNull<Node<T>>>,
-}
+    /// Cursor at the first element not ordered before `element`, or at the ghost position past
+    /// the end when there is none
+    pub fn lower_bound(&self, element: &T) -> Cursor<'_, T, A, C> {
+        let current = unsafe {
+            self.partition_first(|value| self.compare.compare(value, element) == Ordering::Less)
+        };
+        Cursor {
+            tree: self,
+            current,
+        }
+    }
 
-impl<T: Ord + std::fmt::Debug> NilNode<T> {
-    fn new() -> NonNull<NilNode<T>> {
-        unsafe {
-            NonNull::new_unchecked(Box::into_raw(Box::new(NilNode {
-                color: Color::Black,
-                parent: None,
-            })))
+    /// Cursor at the first element ordered after `element`, or at the ghost position past the end
+    pub fn upper_bound(&self, element: &T) -> Cursor<'_, T, A, C> {
+        let current = unsafe {
+            self.partition_first(|value| self.compare.compare(value, element) != Ordering::Greater)
+        };
+        Cursor {
+            tree: self,
+            current,
         }
     }
-}
 
-impl<T: Ord + std::fmt::Debug + Clone> Node<T> {
-    fn new(element: T, parent: Link<T>, nil: Link<T>) -> NonNull<Node<T>> {
-        unsafe {
-            NonNull::new_unchecked(Box::into_raw(Box::new(Node {
-                value: element,
-                color: Color::Red,
-                left: nil,
-                right: nil,
-                parent,
-            })))
+    pub fn lower_bound_mut(&mut self, element: &T) -> CursorMut<'_, T, A, C> {
+        let current = self.lower_bound(element).current;
+        CursorMut {
+            tree: self,
+            current,
         }
     }
 
-    fn node_drop(node: NonNull<Node<T>>) {
-        unsafe {
-            let boxed_node = Box::from_raw(node.as_ptr());
+    pub fn upper_bound_mut(&mut self, element: &T) -> CursorMut<'_, T, A, C> {
+        let current = self.upper_bound(element).current;
+        CursorMut {
+            tree: self,
+            current,
+        }
+    }
 
-            if let Link::Real(left) = boxed_node.left {
-                Self::node_drop(left);
-            }
+    /// Node after `node`, where `None` is the ghost position between the last and first nodes
+    unsafe fn node_after(&self, node: Option<NonNull<Node<T, A>>>) -> Option<NonNull<Node<T, A>>> {
+        match node {
+            Some(node) => Node::next_node(node),
+            None => self.first_node(),
+        }
+    }
 
-            if let Link::Real(right) = boxed_node.right {
-                Self::node_drop(right);
-            }
+    unsafe fn node_before(&self, node: Option<NonNull<Node<T, A>>>) -> Option<NonNull<Node<T, A>>> {
+        match node {
+            Some(node) => Node::prev_node(node),
+            None => self.last_node(),
         }
     }
 
-    fn print(&self, indent: &str, is_final: bool, append: &str) {
-        unsafe {
-            println!("{indent}+- {:?} # {:?} - {append}", self.value, self.color);
-            if let Link::Real(left) = self.left {
-                let new_indent = format!("{indent}{}", if is_final { "   " } else { "|  " });
-                left.as_ref().print(&new_indent, self.right.is_nil(), "L");
+    /// Leftmost node for which `is_before` is false, where `is_before` holds for a prefix of the
+    /// in-order sequence
+    pub(crate) unsafe fn partition_first(
+        &self,
+        mut is_before: impl FnMut(&T) -> bool,
+    ) -> Option<NonNull<Node<T, A>>> {
+        let mut candidate = None;
+        let mut traverse_node = self.root;
+        while let Link::Real(node) = traverse_node {
+            if is_before(&(*node.as_ptr()).value) {
+                traverse_node = (*node.as_ptr()).right;
+            } else {
+                candidate = Some(node);
+                traverse_node = (*node.as_ptr()).left;
             }
+        }
 
-            if let Link::Real(right) = self.right {
-                let new_indent = format!("{indent}{}", if is_final { "   " } else { "|  " });
-                right.as_ref().print(&new_indent, true, "R");
+        candidate
+    }
+
+    /// Rightmost node for which `is_after` is false, where `is_after` holds for a suffix of the
+    /// in-order sequence
+    pub(crate) unsafe fn partition_last(
+        &self,
+        mut is_after: impl FnMut(&T) -> bool,
+    ) -> Option<NonNull<Node<T, A>>> {
+        let mut candidate = None;
+        let mut traverse_node = self.root;
+        while let Link::Real(node) = traverse_node {
+            if is_after(&(*node.as_ptr()).value) {
+                traverse_node = (*node.as_ptr()).left;
+            } else {
+                candidate = Some(node);
+                traverse_node = (*node.as_ptr()).right;
             }
         }
+
+        candidate
     }
 
-    fn in_order_vec(&self) -> Vec<T> {
-        unsafe {
-            if self.left.is_nil() && self.right.is_nil() {
-                return vec![self.value.clone()];
-            }
+    pub(crate) fn root_node(&self) -> Option<&Node<T, A>> {
+        match self.root {
+            Link::Real(root) => unsafe { Some(&*root.as_ptr()) },
+            Link::Nil => None,
+        }
+    }
 
-            let left_vec = if let Link::Real(left) = self.left {
-                left.as_ref().in_order_vec()
-            } else {
-                Vec::new()
-            };
-            let right_vec = if let Link::Real(right) = self.right {
-                right.as_ref().in_order_vec()
-            } else {
-                Vec::new()
-            };
+    unsafe fn first_node(&self) -> Option<NonNull<Node<T, A>>> {
+        match self.root {
+            Link::Real(root) => Some(Node::follow_left(root)),
+            Link::Nil => None,
+        }
+    }
 
-            left_vec
-                .into_iter()
-                .chain(std::iter::once(self.value.clone()))
-                .chain(right_vec.into_iter())
-                .collect()
+    unsafe fn last_node(&self) -> Option<NonNull<Node<T, A>>> {
+        match self.root {
+            Link::Real(root) => Some(Node::follow_right(root)),
+            Link::Nil => None,
         }
     }
+}
 
-    fn in_order_successor(node: NonNull<Node<T>>) -> NonNull<Node<T>> {
-        unsafe { Self::follow_left((*node.as_ptr()).right.into_node()) }
+impl<T: std::fmt::Debug, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    pub fn print(&self) {
+        print!("{}", self.display());
     }
 
-    fn follow_left(node: NonNull<Node<T>>) -> NonNull<Node<T>> {
-        unsafe {
-            if let Link::Real(left) = (*node.as_ptr()).left {
-                Node::follow_left(left)
-            } else {
-                node
+    /// Writes the indented dump `print` shows, one node per line with its colour and side
+    pub fn render(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        match self.root_node() {
+            Some(root) => root.render(out, "", true, ""),
+            None => Ok(()),
+        }
+    }
+
+    /// Formats as the dump written by [`RBTree::render`]
+    pub fn display(&self) -> Display<'_, T, A, C> {
+        Display { tree: self }
+    }
+
+    /// Graphviz digraph of the tree with nodes filled in their colour
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot, false)
+            .expect("Writing to a String cannot fail");
+        dot
+    }
+
+    /// Like [`RBTree::to_dot`], with every nil leaf drawn as a point
+    pub fn to_dot_with_nil_leaves(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot, true)
+            .expect("Writing to a String cannot fail");
+        dot
+    }
+
+    pub fn write_dot(&self, out: &mut impl std::fmt::Write, nil_leaves: bool) -> std::fmt::Result {
+        writeln!(out, "digraph RBTree {{")?;
+        writeln!(out, "    node [style=filled, fontcolor=white];")?;
+
+        let mut next_id = 0;
+        let mut stack = Vec::new();
+        if let Some(root) = self.root_node() {
+            stack.push((root, next_id));
+            next_id += 1;
+        }
+
+        while let Some((node, id)) = stack.pop() {
+            let label = format!("{:?}", node.value)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            let fill = match node.color {
+                Color::Red => "red",
+                Color::Black => "black",
+            };
+            writeln!(out, "    n{id} [label=\"{label}\", fillcolor={fill}];")?;
+
+            // Children are numbered left to right, then pushed in reverse so the left subtree is
+            // written first
+            let children = [node.left_node(), node.right_node()];
+            let mut child_ids = [0; 2];
+            for (child, child_id) in children.iter().zip(child_ids.iter_mut()) {
+                *child_id = next_id;
+                next_id += 1;
+                match child {
+                    Some(_) => writeln!(out, "    n{id} -> n{child_id};")?,
+                    None if nil_leaves => {
+                        writeln!(out, "    nil{child_id} [shape=point];")?;
+                        writeln!(out, "    n{id} -> nil{child_id};")?;
+                    }
+                    None => {}
+                }
+            }
+            for (child, child_id) in children.into_iter().zip(child_ids).rev() {
+                if let Some(child) = child {
+                    stack.push((child, child_id));
+                }
             }
         }
+
+        writeln!(out, "}}")
     }
+}
 
-    fn height(&self) -> u32 {
+pub struct Display<'a, T, A: Augmentation<T> = (), C: Compare<T> = Natural> {
+    tree: &'a RBTree<T, A, C>,
+}
+
+impl<'a, T: std::fmt::Debug, A: Augmentation<T>, C: Compare<T>> std::fmt::Display
+    for Display<'a, T, A, C>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.tree.render(f)
+    }
+}
+
+/// A detached subtree together with its black height
+type Piece<T, A> = (Link<T, A>, usize);
+
+/// The pieces before and after a split point, and the node found at it
+type Split<T, A> = (Piece<T, A>, Option<NonNull<Node<T, A>>>, Piece<T, A>);
+
+/// Split and join work on detached subtrees with black roots, using `self.root` as the working
+/// root while rotating so the usual fix-up code applies unchanged. Every operation below costs
+/// O(log n) per join, which gives O(m log(n / m + 1)) for the set operations.
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<T, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    /// Builds the tree holding the elements of `left`, then `pivot`, then the elements of `right`.
+    /// Panics unless every element of `left` is ordered before `pivot` and every element of
+    /// `right` after it.
+    pub fn join(mut left: Self, pivot: T, mut right: Self) -> Self {
         unsafe {
-            if self.left.is_nil() && self.right.is_nil() {
-                return 1;
+            if let Some(max) = left.last_node() {
+                assert!(
+                    left.compare.compare(&(*max.as_ptr()).value, &pivot) != Ordering::Greater,
+                    "Left tree has elements after the pivot"
+                );
+            }
+            if let Some(min) = right.first_node() {
+                assert!(
+                    left.compare.compare(&pivot, &(*min.as_ptr()).value) != Ordering::Greater,
+                    "Right tree has elements before the pivot"
+                );
             }
 
-            let left_height = if let Link::Real(left) = self.left {
-                left.as_ref().height()
+            // The larger input keeps its handles, the smaller one's go stale
+            if right.len() > left.len() {
+                std::mem::swap(&mut left.handles, &mut right.handles);
+                right.handles.release_subtree(left.root);
             } else {
-                0
-            };
+                right.handles.release_subtree(right.root);
+            }
 
-            let right_height = if let Link::Real(right) = self.right {
-                right.as_ref().height()
-            } else {
-                0
-            };
+            let left_piece = left.take_piece();
+            let right_piece = right.take_piece();
+            let pivot = Node::new(pivot, Link::Nil, Link::Nil);
+            let joined = left.join_pieces(left_piece, pivot, right_piece);
+            left.set_piece(joined);
+        }
 
-            left_height.max(right_height) + 1
+        left
+    }
+
+    /// Every element of either tree. Where both hold an equal element, the one from `self` is
+    /// kept. Handles into `self` stay valid.
+    pub fn union(mut self, mut other: Self) -> Self {
+        unsafe {
+            other.handles.release_subtree(other.root);
+            let (ours, theirs) = (self.take_piece(), other.take_piece());
+            let union = self.union_pieces(ours, theirs);
+            self.set_piece(union);
         }
+
+        self
     }
 
-    fn min_height(&self) -> u32 {
+    /// Elements of `self` that have an equal element in `other`. Handles into `self` stay valid
+    /// for the elements kept.
+    pub fn intersection(mut self, mut other: Self) -> Self {
         unsafe {
-            if self.left.is_nil() && self.right.is_nil() {
-                return 1;
-            }
+            let (ours, theirs) = (self.take_piece(), other.take_piece());
+            let intersection = self.intersection_pieces(ours, theirs);
+            self.set_piece(intersection);
+        }
 
-            let left_height = if let Link::Real(left) = self.left {
-                left.as_ref().min_height()
-            } else {
-                0
-            };
+        self
+    }
 
-            let right_height = if let Link::Real(right) = self.right {
-                right.as_ref().min_height()
-            } else {
-                0
-            };
+    /// Elements of `self` that have no equal element in `other`. Handles into `self` stay valid
+    /// for the elements kept.
+    pub fn difference(mut self, mut other: Self) -> Self {
+        unsafe {
+            let (ours, theirs) = (self.take_piece(), other.take_piece());
+            let difference = self.difference_pieces(ours, theirs);
+            self.set_piece(difference);
+        }
 
-            left_height.min(right_height) + 1
+        self
+    }
+
+    /// Moves every element of `other` in after those of `self` without consulting the
+    /// comparator, which the caller has to keep consistent with. Handles into `other` go stale.
+    pub(crate) fn append(&mut self, mut other: Self) {
+        unsafe {
+            other.handles.release_subtree(other.root);
+            let (ours, theirs) = (self.take_piece(), other.take_piece());
+            let appended = self.join_pieces_without_pivot(ours, theirs);
+            self.set_piece(appended);
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::collections::HashSet;
+    /// Empties the tree, handing its nodes over as a piece
+    unsafe fn take_piece(&mut self) -> Piece<T, A> {
+        let mut black_height = 0;
+        let mut traverse = self.root;
+        while let Link::Real(node) = traverse {
+            if (*node.as_ptr()).color == Color::Black {
+                black_height += 1;
+            }
+            traverse = (*node.as_ptr()).left;
+        }
 
-    use rand::distr::StandardUniform;
+        self.len = 0;
+        (std::mem::replace(&mut self.root, Link::Nil), black_height)
+    }
 
-    use crate::data_structures::rbtree::Color;
+    fn set_piece(&mut self, (root, _): Piece<T, A>) {
+        self.root = root;
+        self.len = root.size();
+    }
 
-    use super::{NilNode, Node, RBTree};
+    /// Cuts both children off `node`, blackening their roots so they are valid pieces
+    unsafe fn detach_children(
+        node: NonNull<Node<T, A>>,
+        black_height: usize,
+    ) -> (Piece<T, A>, Piece<T, A>) {
+        let child_height = if (*node.as_ptr()).color == Color::Black {
+            black_height - 1
+        } else {
+            black_height
+        };
+
+        let detach = |child: Link<T, A>| match child {
+            Link::Real(child) => {
+                (*child.as_ptr()).parent = Link::Nil;
+                if (*child.as_ptr()).color == Color::Red {
+                    (*child.as_ptr()).color = Color::Black;
+                    (Link::Real(child), child_height + 1)
+                } else {
+                    (Link::Real(child), child_height)
+                }
+            }
+            Link::Nil => (Link::Nil, 0),
+        };
 
-    #[test]
-    fn successfully_construct_empty_tree() {
-        let tree: RBTree<i32> = RBTree::new();
-        assert!(tree.root.is_nil());
+        (
+            detach((*node.as_ptr()).left),
+            detach((*node.as_ptr()).right),
+        )
     }
 
-    #[test]
-    fn insert_case_1_left() {
-        let mut tree = RBTree::new();
-
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-2);
-        tree.insert(-1);
+    /// Links `pivot` red into the spine of the taller piece where the black heights match, then
+    /// repairs the colouring as an insertion would
+    unsafe fn join_pieces(
+        &mut self,
+        (left, left_height): Piece<T, A>,
+        pivot: NonNull<Node<T, A>>,
+        (right, right_height): Piece<T, A>,
+    ) -> Piece<T, A> {
+        let mut parent = Link::Nil;
+        let (mut spine, mut height, target_height) = if left_height >= right_height {
+            (left, left_height, right_height)
+        } else {
+            (right, right_height, left_height)
+        };
+        self.root = spine;
 
-        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0, 2]);
+        while height > target_height || spine.color() == Color::Red {
+            if spine.color() == Color::Black {
+                height -= 1;
+            }
+            parent = spine;
+            spine = if left_height >= right_height {
+                (*spine.into_node().as_ptr()).right
+            } else {
+                (*spine.into_node().as_ptr()).left
+            };
+        }
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let left_right_child = (*left_child.as_ptr()).right.into_node();
-            assert_eq!((*left_right_child.as_ptr()).value, -1);
-            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+        let (pivot_left, pivot_right) = if left_height >= right_height {
+            (spine, right)
+        } else {
+            (left, spine)
+        };
+        for child in [pivot_left, pivot_right] {
+            if let Link::Real(child) = child {
+                (*child.as_ptr()).parent = Link::Real(pivot);
+            }
+        }
+        (*pivot.as_ptr()).left = pivot_left;
+        (*pivot.as_ptr()).right = pivot_right;
+        (*pivot.as_ptr()).parent = parent;
+        (*pivot.as_ptr()).color = Color::Red;
+
+        match parent {
+            Link::Real(parent) if left_height >= right_height => {
+                (*parent.as_ptr()).right = Link::Real(pivot)
+            }
+            Link::Real(parent) => (*parent.as_ptr()).left = Link::Real(pivot),
+            Link::Nil => self.root = Link::Real(pivot),
         }
+
+        Node::update_to_root(pivot);
+        let grew = self.insert_fix(pivot);
+
+        let root = std::mem::replace(&mut self.root, Link::Nil);
+        (root, left_height.max(right_height) + grew as usize)
     }
 
-    #[test]
-    fn insert_case_1_left_alternate() {
-        let mut tree = RBTree::new();
+    /// Joins two pieces without a pivot by borrowing the largest element of `left`
+    unsafe fn join_pieces_without_pivot(
+        &mut self,
+        left: Piece<T, A>,
+        right: Piece<T, A>,
+    ) -> Piece<T, A> {
+        let Link::Real(left_root) = left.0 else {
+            return right;
+        };
+        if right.0.is_nil() {
+            return left;
+        }
 
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-2);
-        tree.insert(-3);
+        let max = Node::follow_right(left_root);
+        let (rest, max, _) = self.split_piece(left, &mut |node| {
+            if node == max {
+                Ordering::Equal
+            } else {
+                Ordering::Less
+            }
+        });
 
-        assert_eq!(tree.in_order_vec(), vec![-3, -2, 0, 2]);
+        self.join_pieces(rest, max.unwrap(), right)
+    }
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let left_left_child = (*left_child.as_ptr()).left.into_node();
-            assert_eq!((*left_left_child.as_ptr()).value, -3);
-            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
+    /// Splits a piece by `locate`, which orders each node against the split point. A located
+    /// node still links to its children, so their sizes can be read. Nodes placed `Less` go left
+    /// and `Greater` go right; the first `Equal` node found is returned on its own with stale
+    /// links.
+    unsafe fn split_piece(
+        &mut self,
+        (link, black_height): Piece<T, A>,
+        locate: &mut impl FnMut(NonNull<Node<T, A>>) -> Ordering,
+    ) -> Split<T, A> {
+        let Link::Real(node) = link else {
+            return ((Link::Nil, 0), None, (Link::Nil, 0));
+        };
+
+        let (left, right) = Self::detach_children(node, black_height);
+        match locate(node) {
+            Ordering::Less => {
+                let (middle_left, found, middle_right) = self.split_piece(right, locate);
+                (
+                    self.join_pieces(left, node, middle_left),
+                    found,
+                    middle_right,
+                )
+            }
+            Ordering::Greater => {
+                let (middle_left, found, middle_right) = self.split_piece(left, locate);
+                (
+                    middle_left,
+                    found,
+                    self.join_pieces(middle_right, node, right),
+                )
+            }
+            Ordering::Equal => (left, Some(node), right),
         }
     }
 
-    #[test]
-    fn insert_case_1_right() {
-        let mut tree = RBTree::new();
+    /// Splits `piece` around the value of `pivot`, which is not part of it
+    unsafe fn split_piece_at(
+        &mut self,
+        piece: Piece<T, A>,
+        pivot: NonNull<Node<T, A>>,
+    ) -> Split<T, A> {
+        // The comparator is only read while the split restructures nodes through `self`
+        let compare: *const C = &self.compare;
+        let key: *const T = &(*pivot.as_ptr()).value;
+        self.split_piece(piece, &mut |node| {
+            (*compare).compare(&(*node.as_ptr()).value, &*key)
+        })
+    }
 
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-2);
-        tree.insert(1);
+    unsafe fn union_pieces(&mut self, ours: Piece<T, A>, theirs: Piece<T, A>) -> Piece<T, A> {
+        let Link::Real(pivot) = theirs.0 else {
+            return ours;
+        };
+        if ours.0.is_nil() {
+            return theirs;
+        }
 
-        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2]);
+        let (their_left, their_right) = Self::detach_children(pivot, theirs.1);
+        let (our_left, duplicate, our_right) = self.split_piece_at(ours, pivot);
+        let pivot = match duplicate {
+            Some(duplicate) => {
+                let _ = Box::from_raw(pivot.as_ptr());
+                duplicate
+            }
+            None => pivot,
+        };
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let right_left_child = (*right_child.as_ptr()).left.into_node();
-            assert_eq!((*right_left_child.as_ptr()).value, 1);
-            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
+        let left = self.union_pieces(our_left, their_left);
+        let right = self.union_pieces(our_right, their_right);
+        self.join_pieces(left, pivot, right)
+    }
+
+    unsafe fn intersection_pieces(
+        &mut self,
+        ours: Piece<T, A>,
+        theirs: Piece<T, A>,
+    ) -> Piece<T, A> {
+        let (Link::Real(_), Link::Real(pivot)) = (ours.0, theirs.0) else {
+            self.handles.release_subtree(ours.0);
+            Self::free_piece(ours);
+            Self::free_piece(theirs);
+            return (Link::Nil, 0);
+        };
+
+        let (their_left, their_right) = Self::detach_children(pivot, theirs.1);
+        let (our_left, kept, our_right) = self.split_piece_at(ours, pivot);
+        let _ = Box::from_raw(pivot.as_ptr());
+
+        let left = self.intersection_pieces(our_left, their_left);
+        let right = self.intersection_pieces(our_right, their_right);
+        match kept {
+            Some(kept) => self.join_pieces(left, kept, right),
+            None => self.join_pieces_without_pivot(left, right),
         }
     }
 
-    #[test]
-    fn insert_case_1_right_alternate() {
-        let mut tree = RBTree::new();
+    unsafe fn difference_pieces(&mut self, ours: Piece<T, A>, theirs: Piece<T, A>) -> Piece<T, A> {
+        let (Link::Real(_), Link::Real(pivot)) = (ours.0, theirs.0) else {
+            Self::free_piece(theirs);
+            return ours;
+        };
+
+        let (their_left, their_right) = Self::detach_children(pivot, theirs.1);
+        let (our_left, removed, our_right) = self.split_piece_at(ours, pivot);
+        let _ = Box::from_raw(pivot.as_ptr());
+        if let Some(removed) = removed {
+            self.handles.release(removed);
+            let _ = Box::from_raw(removed.as_ptr());
+        }
 
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-2);
-        tree.insert(3);
+        let left = self.difference_pieces(our_left, their_left);
+        let right = self.difference_pieces(our_right, their_right);
+        self.join_pieces_without_pivot(left, right)
+    }
 
-        assert_eq!(tree.in_order_vec(), vec![-2, 0, 2, 3]);
+    fn free_piece((link, _): Piece<T, A>) {
+        if let Link::Real(root) = link {
+            Node::node_drop(root);
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T> + Clone> RBTree<T, A, C> {
+    /// Moves every element ordered at or after `element` into a new tree, keeping the rest.
+    /// Handles to the moved elements go stale.
+    pub fn split_off(&mut self, element: &T) -> Self {
+        let compare: *const C = &self.compare;
+        self.split_off_where(&mut |node| unsafe {
+            match (*compare).compare(&(*node.as_ptr()).value, element) {
+                Ordering::Less => Ordering::Less,
+                _ => Ordering::Greater,
+            }
+        })
+    }
+
+    /// Moves the elements from position `index` on into a new tree, keeping the first `index`
+    pub(crate) fn split_off_at(&mut self, index: usize) -> Self {
+        let mut offset = 0;
+        self.split_off_where(&mut |node| {
+            let position = offset + unsafe { (*node.as_ptr()).left.size() };
+            if position < index {
+                offset = position + 1;
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        })
+    }
+
+    /// Moves the elements `locate` places after the split point into a new tree. This is
+    /// O(log n) unless some element still has a handle, in which case the moved elements are
+    /// walked to retire theirs.
+    fn split_off_where(
+        &mut self,
+        locate: &mut impl FnMut(NonNull<Node<T, A>>) -> Ordering,
+    ) -> Self {
+        let mut other = Self::with_comparator_augmented(self.compare.clone());
+
+        unsafe {
+            let piece = self.take_piece();
+            let (before, _, after) = self.split_piece(piece, locate);
+            self.handles.release_subtree(after.0);
+            self.set_piece(before);
+            other.set_piece(after);
+        }
+
+        other
+    }
+}
+
+/// Shape of a tree that passed [`RBTree::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    pub len: usize,
+    /// Nodes on the longest root-to-leaf path
+    pub height: usize,
+    /// Black nodes on every root-to-leaf path
+    pub black_height: usize,
+    pub red_nodes: usize,
+}
+
+/// The first broken invariant [`RBTree::validate`] ran into, naming the nodes involved by value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RbViolation<T> {
+    RedRoot {
+        root: T,
+    },
+    RootHasParent {
+        root: T,
+    },
+    OutOfOrder {
+        before: T,
+        after: T,
+    },
+    RedChildOfRed {
+        parent: T,
+        child: T,
+    },
+    BlackHeightMismatch {
+        node: T,
+        left: usize,
+        right: usize,
+    },
+    WrongParent {
+        node: T,
+    },
+    WrongSize {
+        node: T,
+        stored: usize,
+        actual: usize,
+    },
+    WrongLen {
+        stored: usize,
+        actual: usize,
+    },
+    /// The nil parent slot `delete_fix` climbs from was left pointing at a node
+    NilParentSet,
+}
+
+impl<T: std::fmt::Debug> std::fmt::Display for RbViolation<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RbViolation::RedRoot { root } => write!(f, "root {root:?} is red"),
+            RbViolation::RootHasParent { root } => write!(f, "root {root:?} has a parent"),
+            RbViolation::OutOfOrder { before, after } => {
+                write!(
+                    f,
+                    "{before:?} is stored before {after:?} but ordered after it"
+                )
+            }
+            RbViolation::RedChildOfRed { parent, child } => {
+                write!(f, "red node {child:?} has red parent {parent:?}")
+            }
+            RbViolation::BlackHeightMismatch { node, left, right } => write!(
+                f,
+                "subtrees of {node:?} have black heights {left} and {right}"
+            ),
+            RbViolation::WrongParent { node } => {
+                write!(f, "parent pointer of {node:?} does not match its parent")
+            }
+            RbViolation::WrongSize {
+                node,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "subtree of {node:?} has size {actual} but stores {stored}"
+            ),
+            RbViolation::WrongLen { stored, actual } => {
+                write!(f, "tree has {actual} elements but stores length {stored}")
+            }
+            RbViolation::NilParentSet => write!(f, "nil parent slot was not cleared"),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for RbViolation<T> {}
+
+/// What [`RBTree::validate`] found in one subtree
+struct SubtreeSummary<T, A: Augmentation<T>> {
+    first: NonNull<Node<T, A>>,
+    last: NonNull<Node<T, A>>,
+    size: usize,
+    height: usize,
+    black_height: usize,
+    red_nodes: usize,
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<T: Clone, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    /// Starts recording every fix-up step into a fresh trace
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace {
+            events: Vec::new(),
+            clone: T::clone,
+        });
+    }
+
+    /// Checks every red-black and bookkeeping invariant in O(n)
+    pub fn validate(&self) -> Result<TreeStats, RbViolation<T>> {
+        unsafe {
+            if (*self.nil.as_ptr()).parent.is_some() {
+                return Err(RbViolation::NilParentSet);
+            }
+
+            let Link::Real(root) = self.root else {
+                return match self.len {
+                    0 => Ok(TreeStats {
+                        len: 0,
+                        height: 0,
+                        black_height: 0,
+                        red_nodes: 0,
+                    }),
+                    stored => Err(RbViolation::WrongLen { stored, actual: 0 }),
+                };
+            };
+
+            let root_value = || (*root.as_ptr()).value.clone();
+            if (*root.as_ptr()).color == Color::Red {
+                return Err(RbViolation::RedRoot { root: root_value() });
+            }
+            if !(*root.as_ptr()).parent.is_nil() {
+                return Err(RbViolation::RootHasParent { root: root_value() });
+            }
+
+            let summary = self.validate_subtree(root)?;
+            if summary.size != self.len {
+                return Err(RbViolation::WrongLen {
+                    stored: self.len,
+                    actual: summary.size,
+                });
+            }
+
+            Ok(TreeStats {
+                len: summary.size,
+                height: summary.height,
+                black_height: summary.black_height,
+                red_nodes: summary.red_nodes,
+            })
+        }
+    }
+
+    unsafe fn validate_subtree(
+        &self,
+        node: NonNull<Node<T, A>>,
+    ) -> Result<SubtreeSummary<T, A>, RbViolation<T>> {
+        let value = |node: NonNull<Node<T, A>>| (*node.as_ptr()).value.clone();
+        let is_red = (*node.as_ptr()).color == Color::Red;
+
+        let mut children = [None, None];
+        for (summary, child) in children
+            .iter_mut()
+            .zip([(*node.as_ptr()).left, (*node.as_ptr()).right])
+        {
+            let Link::Real(child) = child else {
+                continue;
+            };
+
+            if (*child.as_ptr()).parent != Link::Real(node) {
+                return Err(RbViolation::WrongParent { node: value(child) });
+            }
+            if is_red && (*child.as_ptr()).color == Color::Red {
+                return Err(RbViolation::RedChildOfRed {
+                    parent: value(node),
+                    child: value(child),
+                });
+            }
+            *summary = Some(self.validate_subtree(child)?);
+        }
+        let [left, right] = children;
+
+        if let Some(left) = &left
+            && self
+                .compare
+                .compare(&(*left.last.as_ptr()).value, &(*node.as_ptr()).value)
+                == Ordering::Greater
+        {
+            return Err(RbViolation::OutOfOrder {
+                before: value(left.last),
+                after: value(node),
+            });
+        }
+        if let Some(right) = &right
+            && self
+                .compare
+                .compare(&(*node.as_ptr()).value, &(*right.first.as_ptr()).value)
+                == Ordering::Greater
+        {
+            return Err(RbViolation::OutOfOrder {
+                before: value(node),
+                after: value(right.first),
+            });
+        }
+
+        let black_height = |summary: &Option<SubtreeSummary<T, A>>| {
+            summary.as_ref().map_or(0, |summary| summary.black_height)
+        };
+        if black_height(&left) != black_height(&right) {
+            return Err(RbViolation::BlackHeightMismatch {
+                node: value(node),
+                left: black_height(&left),
+                right: black_height(&right),
+            });
+        }
+
+        let field = |summary: &Option<SubtreeSummary<T, A>>,
+                     f: fn(&SubtreeSummary<T, A>) -> usize| {
+            summary.as_ref().map_or(0, f)
+        };
+        let size = field(&left, |summary| summary.size) + field(&right, |summary| summary.size) + 1;
+        if (*node.as_ptr()).size != size {
+            return Err(RbViolation::WrongSize {
+                node: value(node),
+                stored: (*node.as_ptr()).size,
+                actual: size,
+            });
+        }
+
+        Ok(SubtreeSummary {
+            first: left.as_ref().map_or(node, |left| left.first),
+            last: right.as_ref().map_or(node, |right| right.last),
+            size,
+            height: field(&left, |summary| summary.height)
+                .max(field(&right, |summary| summary.height))
+                + 1,
+            black_height: black_height(&left) + !is_red as usize,
+            red_nodes: field(&left, |summary| summary.red_nodes)
+                + field(&right, |summary| summary.red_nodes)
+                + is_red as usize,
+        })
+    }
+}
+
+/// Inputs at least this long are sorted and built in bulk instead of inserted one at a time
+const BULK_BUILD_THRESHOLD: usize = 32;
+
+impl<T, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    /// Replaces the contents of an empty tree with `values` in O(n). Panics if they are not
+    /// sorted by the tree's comparator.
+    fn build_sorted(&mut self, values: Vec<T>) {
+        debug_assert!(self.is_empty());
+        assert!(
+            values
+                .windows(2)
+                .all(|pair| self.compare.compare(&pair[0], &pair[1]) != Ordering::Greater),
+            "Values are not sorted"
+        );
+
+        // Midpoint splits fill every level above `red_depth`, so colouring only the partial
+        // bottom level red gives every path the same number of black nodes
+        let len = values.len();
+        let red_depth = (len + 1).ilog2() as usize;
+        self.root = Self::build_balanced(&mut values.into_iter(), len, 0, red_depth);
+        self.len = len;
+    }
+
+    /// Builds a subtree from the next `len` values, in order
+    fn build_balanced(
+        values: &mut impl Iterator<Item = T>,
+        len: usize,
+        depth: usize,
+        red_depth: usize,
+    ) -> Link<T, A> {
+        if len == 0 {
+            return Link::Nil;
+        }
+
+        let left_len = len / 2;
+        let left = Self::build_balanced(values, left_len, depth + 1, red_depth);
+        let node = Node::new(values.next().unwrap(), Link::Nil, Link::Nil);
+        let right = Self::build_balanced(values, len - left_len - 1, depth + 1, red_depth);
+
+        unsafe {
+            for child in [left, right] {
+                if let Link::Real(child) = child {
+                    (*child.as_ptr()).parent = Link::Real(node);
+                }
+            }
+            (*node.as_ptr()).left = left;
+            (*node.as_ptr()).right = right;
+            if depth < red_depth {
+                (*node.as_ptr()).color = Color::Black;
+            }
+        }
+        Node::update(node);
+
+        Link::Real(node)
+    }
+}
+
+/// Lookups by any borrowed form of the element, e.g. `&str` for a tree of `String`s. Only
+/// available with the natural order, since [`Borrow`] only promises that `Q` orders like `T`.
+impl<T: Ord, A: Augmentation<T>> RBTree<T, A> {
+    pub fn contains<Q: Ord + ?Sized>(&self, element: &Q) -> bool
+    where
+        T: Borrow<Q>,
+    {
+        self.get(element).is_some()
+    }
+
+    pub fn get<Q: Ord + ?Sized>(&self, element: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+    {
+        unsafe {
+            self.search_by(|value| element.cmp(value.borrow()))
+                .map(|node| &(*node.as_ptr()).value)
+        }
+    }
+
+    /// Removes the element and hands it back
+    pub fn take<Q: Ord + ?Sized>(&mut self, element: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+    {
+        self.remove_by(|value| element.cmp(value.borrow()))
+    }
+}
+
+impl<T: Ord, A: Augmentation<T>> RBTree<T, A>
+where
+    for<'a> &'a T: std::ops::Sub<&'a T, Output = T>,
+{
+    /// Return the closest element to the target, even if it is not exactly the target
+    pub fn get_nearest(&self, element: &T) -> Option<&T> {
+        self.nearest_by(
+            element,
+            |lhs, rhs| if lhs > rhs { lhs - rhs } else { rhs - lhs },
+        )
+    }
+}
+
+/// Distance between two elements, used by [`RBTree::nearest`] and [`RBTree::k_nearest`]
+pub trait Distance {
+    type Output: PartialOrd;
+
+    fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! impl_distance_for_integers {
+    ($($integer:ty => $unsigned:ty),*) => {
+        $(
+            impl Distance for $integer {
+                type Output = $unsigned;
+
+                fn distance(&self, other: &Self) -> Self::Output {
+                    self.abs_diff(*other)
+                }
+            }
+        )*
+    };
+}
+
+impl_distance_for_integers!(
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize,
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize
+);
+
+impl Distance for std::time::Duration {
+    type Output = std::time::Duration;
+
+    fn distance(&self, other: &Self) -> Self::Output {
+        self.abs_diff(*other)
+    }
+}
+
+impl Distance for std::time::Instant {
+    type Output = std::time::Duration;
+
+    fn distance(&self, other: &Self) -> Self::Output {
+        if self > other {
+            self.duration_since(*other)
+        } else {
+            other.duration_since(*self)
+        }
+    }
+}
+
+impl<T: Distance, A: Augmentation<T>, C: Compare<T>> RBTree<T, A, C> {
+    /// Closest element to `target`, preferring the smaller one on ties
+    pub fn nearest(&self, target: &T) -> Option<&T> {
+        self.nearest_by(target, T::distance)
+    }
+
+    /// The `k` closest elements to `target`, closest first
+    pub fn k_nearest(&self, target: &T, k: usize) -> Vec<&T> {
+        self.k_nearest_by(target, k, T::distance)
+    }
+}
+
+pub struct Iter<'a, T, A: Augmentation<T> = ()> {
+    front: Option<NonNull<Node<T, A>>>,
+    back: Option<NonNull<Node<T, A>>>,
+    remaining: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T, A: Augmentation<T>> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        unsafe {
+            let node = self.front?;
+            self.remaining -= 1;
+            self.front = Node::next_node(node);
+            Some(&(*node.as_ptr()).value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, A: Augmentation<T>> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        unsafe {
+            let node = self.back?;
+            self.remaining -= 1;
+            self.back = Node::prev_node(node);
+            Some(&(*node.as_ptr()).value)
+        }
+    }
+}
+
+impl<'a, T, A: Augmentation<T>> ExactSizeIterator for Iter<'a, T, A> {}
+
+impl<'a, T, A: Augmentation<T>, C: Compare<T>> IntoIterator for &'a RBTree<T, A, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Range<'a, T, A: Augmentation<T> = ()> {
+    front: Option<NonNull<Node<T, A>>>,
+    back: Option<NonNull<Node<T, A>>>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T, A: Augmentation<T>> Iterator for Range<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            let node = self.front?;
+            if self.front == self.back {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.front = Node::next_node(node);
+            }
+            Some(&(*node.as_ptr()).value)
+        }
+    }
+}
+
+impl<'a, T, A: Augmentation<T>> DoubleEndedIterator for Range<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        unsafe {
+            let node = self.back?;
+            if self.front == self.back {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.back = Node::prev_node(node);
+            }
+            Some(&(*node.as_ptr()).value)
+        }
+    }
+}
+
+/// Read-only position in an [`RBTree`]. Besides the elements there is a ghost position past the
+/// end, from which `move_next` wraps to the first element and `move_prev` to the last.
+pub struct Cursor<'a, T, A: Augmentation<T> = (), C: Compare<T> = Natural> {
+    tree: &'a RBTree<T, A, C>,
+    current: Option<NonNull<Node<T, A>>>,
+}
+
+impl<'a, T, A: Augmentation<T>, C: Compare<T>> Clone for Cursor<'a, T, A, C> {
+    fn clone(&self) -> Self {
+        Cursor {
+            tree: self.tree,
+            current: self.current,
+        }
+    }
+}
+
+impl<'a, T, A: Augmentation<T>, C: Compare<T>> Cursor<'a, T, A, C> {
+    /// `None` at the ghost position
+    pub fn current(&self) -> Option<&'a T> {
+        self.current.map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        unsafe {
+            self.tree
+                .node_after(self.current)
+                .map(|node| &(*node.as_ptr()).value)
+        }
+    }
+
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        unsafe {
+            self.tree
+                .node_before(self.current)
+                .map(|node| &(*node.as_ptr()).value)
+        }
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = unsafe { self.tree.node_after(self.current) };
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = unsafe { self.tree.node_before(self.current) };
+    }
+}
+
+/// Position in an [`RBTree`] that can also remove the current element and insert next to it
+/// without searching from the root. Elements are never handed out mutably, so the ordering
+/// cannot be broken through the cursor.
+pub struct CursorMut<'a, T, A: Augmentation<T> = (), C: Compare<T> = Natural> {
+    tree: &'a mut RBTree<T, A, C>,
+    current: Option<NonNull<Node<T, A>>>,
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<'a, T, A: Augmentation<T>, C: Compare<T>> CursorMut<'a, T, A, C> {
+    /// `None` at the ghost position
+    pub fn current(&self) -> Option<&T> {
+        self.current.map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    pub fn peek_next(&self) -> Option<&T> {
+        self.as_cursor().peek_next()
+    }
+
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.as_cursor().peek_prev()
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = unsafe { self.tree.node_after(self.current) };
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = unsafe { self.tree.node_before(self.current) };
+    }
+
+    pub fn as_cursor(&self) -> Cursor<'_, T, A, C> {
+        Cursor {
+            tree: self.tree,
+            current: self.current,
+        }
+    }
+
+    /// Removes the current element and moves on to the next one. Does nothing at the ghost
+    /// position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let current = self.current?;
+
+        unsafe {
+            // With two children the successor's value is moved into this node before the
+            // successor is freed, so the node itself becomes the next position
+            let has_two_children =
+                !(*current.as_ptr()).left.is_nil() && !(*current.as_ptr()).right.is_nil();
+            let next = Node::next_node(current);
+
+            let value = self.tree.remove_node(current);
+            self.current = if has_two_children {
+                Some(current)
+            } else {
+                next
+            };
+
+            Some(value)
+        }
+    }
+
+    /// Handle to the current element, as [`RBTree::insert_with_handle`] would have returned
+    pub fn current_handle(&mut self) -> Option<Handle> {
+        let current = self.current?;
+        unsafe { Some(self.tree.handles.handle_of(current)) }
+    }
+
+    /// Inserts `element` just before the current position, which stays where it is. Panics if
+    /// that would put it out of order.
+    pub fn insert_before(&mut self, element: T) {
+        unsafe {
+            let prev = self.tree.node_before(self.current);
+            self.assert_between(prev, &element, self.current);
+
+            let node = Node::new(element, Link::Nil, Link::Nil);
+            match (self.current, prev) {
+                (Some(current), _) if (*current.as_ptr()).left.is_nil() => {
+                    self.tree.attach(node, Link::Real(current), true)
+                }
+                (_, Some(prev)) => self.tree.attach(node, Link::Real(prev), false),
+                (_, None) => self.tree.attach(node, Link::Nil, false),
+            }
+        }
+    }
+
+    /// Inserts `element` just after the current position, which stays where it is. Panics if
+    /// that would put it out of order.
+    pub fn insert_after(&mut self, element: T) {
+        unsafe {
+            let next = self.tree.node_after(self.current);
+            self.assert_between(self.current, &element, next);
+
+            let node = Node::new(element, Link::Nil, Link::Nil);
+            match (self.current, next) {
+                (Some(current), _) if (*current.as_ptr()).right.is_nil() => {
+                    self.tree.attach(node, Link::Real(current), false)
+                }
+                (_, Some(next)) => self.tree.attach(node, Link::Real(next), true),
+                (_, None) => self.tree.attach(node, Link::Nil, false),
+            }
+        }
+    }
+
+    unsafe fn assert_between(
+        &self,
+        before: Option<NonNull<Node<T, A>>>,
+        element: &T,
+        after: Option<NonNull<Node<T, A>>>,
+    ) {
+        let compare = &self.tree.compare;
+        if let Some(before) = before {
+            assert!(
+                compare.compare(&(*before.as_ptr()).value, element) != Ordering::Greater,
+                "Inserted element is ordered before its predecessor"
+            );
+        }
+        if let Some(after) = after {
+            assert!(
+                compare.compare(element, &(*after.as_ptr()).value) != Ordering::Greater,
+                "Inserted element is ordered after its successor"
+            );
+        }
+    }
+}
+
+/// Moves the elements out of an [`RBTree`] in order, freeing each node as it is yielded. The
+/// front is always the leftmost node left, so taking it only splices its right subtree into its
+/// place, and the back mirrors that. No rebalancing is needed since nothing searches the tree.
+pub struct IntoIter<T, A: Augmentation<T> = ()> {
+    front: Option<NonNull<Node<T, A>>>,
+    back: Option<NonNull<Node<T, A>>>,
+    len: usize,
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<T, A: Augmentation<T>> IntoIter<T, A> {
+    /// Unlinks and frees `node`, the leftmost node left when `from_front` and the rightmost
+    /// otherwise, returning its value and the node that takes its place at that end
+    unsafe fn take(
+        node: NonNull<Node<T, A>>,
+        from_front: bool,
+    ) -> (T, Option<NonNull<Node<T, A>>>) {
+        let node = Box::from_raw(node.as_ptr());
+        let child = if from_front { node.right } else { node.left };
+
+        if let Link::Real(child) = child {
+            (*child.as_ptr()).parent = node.parent;
+        }
+        if let Link::Real(parent) = node.parent {
+            if from_front {
+                (*parent.as_ptr()).left = child;
+            } else {
+                (*parent.as_ptr()).right = child;
+            }
+        }
+
+        let next = match (child, node.parent) {
+            (Link::Real(child), _) if from_front => Some(Node::follow_left(child)),
+            (Link::Real(child), _) => Some(Node::follow_right(child)),
+            (Link::Nil, Link::Real(parent)) => Some(parent),
+            (Link::Nil, Link::Nil) => None,
+        };
+
+        (node.value, next)
+    }
+}
+
+impl<T, A: Augmentation<T>> Iterator for IntoIter<T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        unsafe {
+            let (value, next) = Self::take(self.front?, true);
+            self.front = next;
+            Some(value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, A: Augmentation<T>> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        unsafe {
+            let (value, next) = Self::take(self.back?, false);
+            self.back = next;
+            Some(value)
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>> ExactSizeIterator for IntoIter<T, A> {}
+
+impl<T, A: Augmentation<T>> Drop for IntoIter<T, A> {
+    fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+
+        unsafe {
+            let Some(mut root) = self.front else {
+                return;
+            };
+            while let Link::Real(parent) = (*root.as_ptr()).parent {
+                root = parent;
+            }
+            Node::node_drop(root);
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T>> IntoIterator for RBTree<T, A, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        unsafe {
+            let iter = IntoIter {
+                front: self.first_node(),
+                back: self.last_node(),
+                len: self.len,
+            };
+            self.root = Link::Nil;
+            self.len = 0;
+            iter
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T> + Default> FromIterator<T> for RBTree<T, A, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::new_augmented();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T>> Extend<T> for RBTree<T, A, C> {
+    /// Large batches into an empty tree are sorted and built in bulk
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut values: Vec<T> = iter.into_iter().collect();
+
+        if self.is_empty() && values.len() >= BULK_BUILD_THRESHOLD {
+            values.sort_by(|lhs, rhs| self.compare.compare(lhs, rhs));
+            self.build_sorted(values);
+        } else {
+            for value in values {
+                self.insert(value);
+            }
+        }
+    }
+}
+
+impl<T: Clone, A: Augmentation<T>, C: Compare<T> + Clone> Clone for RBTree<T, A, C> {
+    /// Deep copy with the same shape and colours as the original. Handles into the original do
+    /// not carry over.
+    fn clone(&self) -> Self {
+        let mut cloned = Self::with_comparator_augmented(self.compare.clone());
+        let Link::Real(root) = self.root else {
+            return cloned;
+        };
+
+        unsafe {
+            let nil = cloned.nil();
+            let cloned_root = Node::clone_detached(root, nil, nil);
+            let mut stack = vec![(root, cloned_root)];
+
+            while let Some((original, copy)) = stack.pop() {
+                if let Link::Real(left) = (*original.as_ptr()).left {
+                    let left_copy = Node::clone_detached(left, Link::Real(copy), nil);
+                    (*copy.as_ptr()).left = Link::Real(left_copy);
+                    stack.push((left, left_copy));
+                }
+
+                if let Link::Real(right) = (*original.as_ptr()).right {
+                    let right_copy = Node::clone_detached(right, Link::Real(copy), nil);
+                    (*copy.as_ptr()).right = Link::Real(right_copy);
+                    stack.push((right, right_copy));
+                }
+            }
+
+            cloned.root = Link::Real(cloned_root);
+            cloned.len = self.len;
+        }
+
+        cloned
+    }
+}
+
+// Trees compare, hash and print by their elements in order, whatever their shape and colouring
+
+impl<T: PartialEq, A: Augmentation<T>, C: Compare<T>> PartialEq for RBTree<T, A, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, A: Augmentation<T>, C: Compare<T>> Eq for RBTree<T, A, C> {}
+
+impl<T: PartialOrd, A: Augmentation<T>, C: Compare<T>> PartialOrd for RBTree<T, A, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, A: Augmentation<T>, C: Compare<T>> Ord for RBTree<T, A, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: std::hash::Hash, A: Augmentation<T>, C: Compare<T>> std::hash::Hash for RBTree<T, A, C> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for element in self.iter() {
+            element.hash(state);
+        }
+    }
+}
+
+impl<T: std::fmt::Debug, A: Augmentation<T>, C: Compare<T>> std::fmt::Debug for RBTree<T, A, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+// SAFETY: an `RBTree` owns every node reachable from `root`, its nil parent slot and its handle
+// table outright, the same way a `Box` owns its contents. No node is shared with another tree, no
+// pointer into the tree is kept anywhere else, and nothing uses thread-local state. Moving the
+// tree therefore moves values of type `T`, `A::Value` and `C`. Methods taking `&self` only read
+// through the pointers (the nil parent slot and handle table are written only through `&mut
+// self`), so sharing the tree shares nothing beyond `&T`, `&A::Value` and `&C`.
+unsafe impl<T, A, C> Send for RBTree<T, A, C>
+where
+    T: Send,
+    A: Augmentation<T>,
+    A::Value: Send,
+    C: Compare<T> + Send,
+{
+}
+
+// SAFETY: see `Send` above
+unsafe impl<T, A, C> Sync for RBTree<T, A, C>
+where
+    T: Sync,
+    A: Augmentation<T>,
+    A::Value: Sync,
+    C: Compare<T> + Sync,
+{
+}
+
+// SAFETY: the iterators only walk the links of a tree they borrow and hand out `&T`, so they are
+// as thread-safe as `&T`
+unsafe impl<'a, T: Sync, A: Augmentation<T>> Send for Iter<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: Augmentation<T>> Sync for Iter<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: Augmentation<T>> Send for Range<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: Augmentation<T>> Sync for Range<'a, T, A> {}
+
+// SAFETY: `IntoIter` owns the nodes it has not yielded yet outright, just as the tree did
+unsafe impl<T, A> Send for IntoIter<T, A>
+where
+    T: Send,
+    A: Augmentation<T>,
+    A::Value: Send,
+{
+}
+unsafe impl<T, A> Sync for IntoIter<T, A>
+where
+    T: Sync,
+    A: Augmentation<T>,
+    A::Value: Sync,
+{
+}
+
+// SAFETY: a cursor is a borrow of its tree plus a position within it, so it is as thread-safe as
+// `&RBTree` or `&mut RBTree`
+unsafe impl<'a, T, A: Augmentation<T>, C: Compare<T>> Send for Cursor<'a, T, A, C> where
+    RBTree<T, A, C>: Sync
+{
+}
+unsafe impl<'a, T, A: Augmentation<T>, C: Compare<T>> Sync for Cursor<'a, T, A, C> where
+    RBTree<T, A, C>: Sync
+{
+}
+unsafe impl<'a, T, A: Augmentation<T>, C: Compare<T>> Send for CursorMut<'a, T, A, C> where
+    RBTree<T, A, C>: Send
+{
+}
+unsafe impl<'a, T, A: Augmentation<T>, C: Compare<T>> Sync for CursorMut<'a, T, A, C> where
+    RBTree<T, A, C>: Sync
+{
+}
+
+impl<T, A: Augmentation<T>, C: Compare<T>> Drop for RBTree<T, A, C> {
+    fn drop(&mut self) {
+        if let Link::Real(root) = self.root {
+            Node::node_drop(root);
+        }
+
+        unsafe {
+            let _ = Box::from_raw(self.nil.as_ptr());
+        }
+    }
+}
+
+/// One step taken while restoring the red-black properties, naming nodes by value. Cases are
+/// numbered as in CLRS. `mirrored` marks the variant where the parent (on insert) or the double
+/// black node (on delete) is a right child.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebalanceEvent<T> {
+    /// 1: red uncle, 2: `node` is an inner child and is rotated outward, 3: outer child
+    InsertCase {
+        case: u8,
+        node: T,
+        mirrored: bool,
+    },
+    /// 1: red sibling, 2: sibling with two black children, 3: sibling with a black far child,
+    /// 4: sibling with a red far child. `node` is the parent of the double black node.
+    DeleteCase {
+        case: u8,
+        node: T,
+        mirrored: bool,
+    },
+    /// Only actual changes of colour are recorded
+    Recolor {
+        node: T,
+        color: Color,
+    },
+    RotateLeft {
+        node: T,
+    },
+    RotateRight {
+        node: T,
+    },
+}
+
+static NEXT_TREE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Slot of a node that has not been registered with a [`HandleTable`] yet
+const UNREGISTERED: u32 = u32::MAX;
+
+/// Refers to one element of the [`RBTree`] that handed it out, until that element is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    tree: u64,
+    slot: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    /// The element has been removed from the tree
+    Stale,
+    /// The handle was handed out by a different tree
+    WrongTree,
+}
+
+impl std::fmt::Display for HandleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandleError::Stale => write!(f, "handle refers to a removed element"),
+            HandleError::WrongTree => write!(f, "handle belongs to a different tree"),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+struct HandleSlot<T, A: Augmentation<T>> {
+    node: Option<NonNull<Node<T, A>>>,
+    generation: u32,
+}
+
+/// Maps handle slots to the nodes currently holding their elements. A node only gets a slot once
+/// a handle to it is asked for, so trees built without handles pay nothing for them. A slot's
+/// generation is bumped whenever its element leaves the tree, so older handles to it no longer
+/// match.
+struct HandleTable<T, A: Augmentation<T>> {
+    tree: u64,
+    slots: Vec<HandleSlot<T, A>>,
+    free: Vec<u32>,
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+impl<T, A: Augmentation<T>> HandleTable<T, A> {
+    fn new() -> Self {
+        Self {
+            tree: NEXT_TREE_ID.fetch_add(1, AtomicOrdering::Relaxed),
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Whether no element has a slot, so there are no handles left to retire
+    fn is_empty(&self) -> bool {
+        self.slots.len() == self.free.len()
+    }
+
+    unsafe fn register(&mut self, node: NonNull<Node<T, A>>) {
+        let slot = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(HandleSlot {
+                node: None,
+                generation: 0,
+            });
+            (self.slots.len() - 1) as u32
+        });
+
+        self.slots[slot as usize].node = Some(node);
+        (*node.as_ptr()).slot = slot;
+    }
+
+    unsafe fn release(&mut self, node: NonNull<Node<T, A>>) {
+        let slot = std::mem::replace(&mut (*node.as_ptr()).slot, UNREGISTERED);
+        if slot == UNREGISTERED {
+            return;
+        }
+
+        let entry = &mut self.slots[slot as usize];
+        entry.node = None;
+        entry.generation = entry.generation.wrapping_add(1);
+        self.free.push(slot);
+    }
+
+    /// Points the slot of `node` back at it after its element moved there from another node
+    unsafe fn relink(&mut self, node: NonNull<Node<T, A>>) {
+        let slot = (*node.as_ptr()).slot;
+        if slot != UNREGISTERED {
+            self.slots[slot as usize].node = Some(node);
+        }
+    }
+
+    /// Registers `node` the first time a handle to it is asked for
+    unsafe fn handle_of(&mut self, node: NonNull<Node<T, A>>) -> Handle {
+        if (*node.as_ptr()).slot == UNREGISTERED {
+            self.register(node);
+        }
+
+        let slot = (*node.as_ptr()).slot;
+        Handle {
+            tree: self.tree,
+            slot,
+            generation: self.slots[slot as usize].generation,
+        }
+    }
+
+    fn resolve(&self, handle: Handle) -> Result<NonNull<Node<T, A>>, HandleError> {
+        if handle.tree != self.tree {
+            return Err(HandleError::WrongTree);
+        }
+
+        match self.slots.get(handle.slot as usize) {
+            Some(HandleSlot {
+                node: Some(node),
+                generation,
+            }) if *generation == handle.generation => Ok(*node),
+            _ => Err(HandleError::Stale),
+        }
+    }
+
+    /// Retires the handles of every node below `link`, skipping the walk when there are none
+    unsafe fn release_subtree(&mut self, link: Link<T, A>) {
+        if !self.is_empty() {
+            self.for_each_node(link, |handles, node| handles.release(node));
+        }
+    }
+
+    unsafe fn for_each_node(
+        &mut self,
+        link: Link<T, A>,
+        mut f: impl FnMut(&mut Self, NonNull<Node<T, A>>),
+    ) {
+        let mut stack = vec![link];
+        while let Some(link) = stack.pop() {
+            if let Link::Real(node) = link {
+                f(self, node);
+                stack.push((*node.as_ptr()).left);
+                stack.push((*node.as_ptr()).right);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Link<T, A: Augmentation<T> = ()> {
+    Real(NonNull<Node<T, A>>),
+    Nil,
+}
+
+impl<T, A: Augmentation<T>> PartialEq for Link<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Link::Real(lhs), Link::Real(rhs)) => lhs == rhs,
+            (Link::Nil, Link::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>> Copy for Link<T, A> {}
+
+impl<T, A: Augmentation<T>> Clone for Link<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, A: Augmentation<T>> Link<T, A> {
+    fn color(&self) -> Color {
+        match self {
+            Link::Real(real) => unsafe { (*real.as_ptr()).color },
+            Link::Nil => Color::Black,
+        }
+    }
+
+    fn into_node(self) -> NonNull<Node<T, A>> {
+        unsafe {
+            let Link::Real(node) = self else {
+                panic!("Unwrapped node is nil")
+            };
+
+            node
+        }
+    }
+
+    fn with_aggregate<R>(&self, f: impl FnOnce(&A::Value) -> R) -> R {
+        match self {
+            Link::Real(real) => unsafe { f(&(*real.as_ptr()).aggregate) },
+            Link::Nil => f(&A::empty()),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Link::Real(real) => unsafe { (*real.as_ptr()).size },
+            Link::Nil => 0,
+        }
+    }
+
+    fn is_nil(&self) -> bool {
+        match self {
+            Link::Real(_) => false,
+            Link::Nil => true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Clone, Copy)]
+pub enum Color {
+    Red,
+    Black,
+}
+
+pub struct Node<T, A: Augmentation<T> = ()> {
+    pub value: T,
+    color: Color,
+    size: usize,
+    aggregate: A::Value,
+    left: Link<T, A>,
+    right: Link<T, A>,
+    parent: Link<T, A>,
+    /// Index into the owning tree's [`HandleTable`]
+    slot: u32,
+}
+
+/// Holds the parent of a nil link while `delete_fix` climbs from it
+pub struct NilNode<T, A: Augmentation<T> = ()> {
+    parent: Option<NonNull<Node<T, A>>>,
+}
+
+impl<T, A: Augmentation<T>> NilNode<T, A> {
+    fn new() -> NonNull<NilNode<T, A>> {
+        unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(NilNode { parent: None }))) }
+    }
+}
+
+impl<T, A: Augmentation<T>> Node<T, A> {
+    fn new(element: T, parent: Link<T, A>, nil: Link<T, A>) -> NonNull<Node<T, A>> {
+        unsafe {
+            NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                aggregate: A::lift(&element),
+                value: element,
+                color: Color::Red,
+                size: 1,
+                left: nil,
+                right: nil,
+                parent,
+                slot: UNREGISTERED,
+            })))
+        }
+    }
+
+    pub(crate) fn left_node(&self) -> Option<&Node<T, A>> {
+        match self.left {
+            Link::Real(left) => unsafe { Some(&*left.as_ptr()) },
+            Link::Nil => None,
+        }
+    }
+
+    pub(crate) fn right_node(&self) -> Option<&Node<T, A>> {
+        match self.right {
+            Link::Real(right) => unsafe { Some(&*right.as_ptr()) },
+            Link::Nil => None,
+        }
+    }
+
+    pub(crate) fn aggregate(&self) -> &A::Value {
+        &self.aggregate
+    }
+
+    /// Recomputes the subtree size and aggregate from the children
+    fn update(node: NonNull<Node<T, A>>) {
+        unsafe {
+            let node = &mut *node.as_ptr();
+            node.size = node.left.size() + node.right.size() + 1;
+            node.aggregate = A::combine(
+                &node
+                    .left
+                    .with_aggregate(|left| A::combine(left, &A::lift(&node.value))),
+                &node.right.with_aggregate(Clone::clone),
+            );
+        }
+    }
+
+    fn update_to_root(node: NonNull<Node<T, A>>) {
+        unsafe {
+            let mut ancestor = Link::Real(node);
+            while let Link::Real(node) = ancestor {
+                Node::update(node);
+                ancestor = (*node.as_ptr()).parent;
+            }
+        }
+    }
+
+    /// Frees the whole subtree without recursion. Left children are rotated up until the current
+    /// node has none, at which point it is freed and its right child is visited next.
+    fn node_drop(node: NonNull<Node<T, A>>) {
+        unsafe {
+            let mut current = Link::Real(node);
+            while let Link::Real(node) = current {
+                if let Link::Real(left) = (*node.as_ptr()).left {
+                    (*node.as_ptr()).left = (*left.as_ptr()).right;
+                    (*left.as_ptr()).right = Link::Real(node);
+                    current = Link::Real(left);
+                } else {
+                    current = Box::from_raw(node.as_ptr()).right;
+                }
+            }
+        }
+    }
+}
+
+impl<T: Clone, A: Augmentation<T>> Node<T, A> {
+    /// Copies the value, colour and subtree data of `node` into a new node without children
+    fn clone_detached(
+        node: NonNull<Node<T, A>>,
+        parent: Link<T, A>,
+        nil: Link<T, A>,
+    ) -> NonNull<Node<T, A>> {
+        unsafe {
+            let node = &*node.as_ptr();
+            NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+                value: node.value.clone(),
+                color: node.color,
+                size: node.size,
+                aggregate: node.aggregate.clone(),
+                left: nil,
+                right: nil,
+                parent,
+                slot: UNREGISTERED,
+            })))
+        }
+    }
+}
+
+impl<T: std::fmt::Debug, A: Augmentation<T>> Node<T, A> {
+    fn render(
+        &self,
+        out: &mut impl std::fmt::Write,
+        indent: &str,
+        is_final: bool,
+        append: &str,
+    ) -> std::fmt::Result {
+        unsafe {
+            writeln!(
+                out,
+                "{indent}+- {:?} # {:?} - {append}",
+                self.value, self.color
+            )?;
+            if let Link::Real(left) = self.left {
+                let new_indent = format!("{indent}{}", if is_final { "   " } else { "|  " });
+                left.as_ref()
+                    .render(out, &new_indent, self.right.is_nil(), "L")?;
+            }
+
+            if let Link::Real(right) = self.right {
+                let new_indent = format!("{indent}{}", if is_final { "   " } else { "|  " });
+                right.as_ref().render(out, &new_indent, true, "R")?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+impl<T, A: Augmentation<T>> Node<T, A> {
+    fn in_order_successor(node: NonNull<Node<T, A>>) -> NonNull<Node<T, A>> {
+        unsafe { Self::follow_left((*node.as_ptr()).right.into_node()) }
+    }
+
+    fn follow_left(node: NonNull<Node<T, A>>) -> NonNull<Node<T, A>> {
+        unsafe {
+            if let Link::Real(left) = (*node.as_ptr()).left {
+                Node::follow_left(left)
+            } else {
+                node
+            }
+        }
+    }
+
+    fn follow_right(node: NonNull<Node<T, A>>) -> NonNull<Node<T, A>> {
+        unsafe {
+            if let Link::Real(right) = (*node.as_ptr()).right {
+                Node::follow_right(right)
+            } else {
+                node
+            }
+        }
+    }
+
+    /// In-order successor of any node, climbing through the parents when there is no right subtree
+    fn next_node(node: NonNull<Node<T, A>>) -> Option<NonNull<Node<T, A>>> {
+        unsafe {
+            if let Link::Real(right) = (*node.as_ptr()).right {
+                return Some(Node::follow_left(right));
+            }
+
+            let mut child = node;
+            while let Link::Real(parent) = (*child.as_ptr()).parent {
+                if (*parent.as_ptr()).left == Link::Real(child) {
+                    return Some(parent);
+                }
+                child = parent;
+            }
+
+            None
+        }
+    }
+
+    fn prev_node(node: NonNull<Node<T, A>>) -> Option<NonNull<Node<T, A>>> {
+        unsafe {
+            if let Link::Real(left) = (*node.as_ptr()).left {
+                return Some(Node::follow_right(left));
+            }
+
+            let mut child = node;
+            while let Link::Real(parent) = (*child.as_ptr()).parent {
+                if (*parent.as_ptr()).right == Link::Real(child) {
+                    return Some(parent);
+                }
+                child = parent;
+            }
+
+            None
+        }
+    }
+
+    fn height(&self) -> u32 {
+        unsafe {
+            if self.left.is_nil() && self.right.is_nil() {
+                return 1;
+            }
+
+            let left_height = if let Link::Real(left) = self.left {
+                left.as_ref().height()
+            } else {
+                0
+            };
+
+            let right_height = if let Link::Real(right) = self.right {
+                right.as_ref().height()
+            } else {
+                0
+            };
+
+            left_height.max(right_height) + 1
+        }
+    }
+
+    fn min_height(&self) -> u32 {
+        unsafe {
+            if self.left.is_nil() && self.right.is_nil() {
+                return 1;
+            }
+
+            let left_height = if let Link::Real(left) = self.left {
+                left.as_ref().min_height()
+            } else {
+                0
+            };
+
+            let right_height = if let Link::Real(right) = self.right {
+                right.as_ref().min_height()
+            } else {
+                0
+            };
+
+            left_height.min(right_height) + 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use rand::distr::StandardUniform;
+
+    use crate::data_structures::rbtree::Color;
+
+    use std::ops::Bound;
+
+    use super::{
+        Augmentation, ByKey, HandleError, Node, RBTree, RbViolation, RebalanceEvent as Event,
+    };
+
+    struct Sum;
+
+    impl Augmentation<i32> for Sum {
+        type Value = i64;
+
+        fn empty() -> i64 {
+            0
+        }
+
+        fn lift(element: &i32) -> i64 {
+            *element as i64
+        }
+
+        fn combine(left: &i64, right: &i64) -> i64 {
+            left + right
+        }
+    }
+
+    /// Not commutative, so any regrouping that reorders elements shows up
+    struct Concat;
+
+    impl Augmentation<i32> for Concat {
+        type Value = Vec<i32>;
+
+        fn empty() -> Vec<i32> {
+            Vec::new()
+        }
+
+        fn lift(element: &i32) -> Vec<i32> {
+            vec![*element]
+        }
+
+        fn combine(left: &Vec<i32>, right: &Vec<i32>) -> Vec<i32> {
+            left.iter().chain(right).copied().collect()
+        }
+    }
+
+    #[test]
+    fn successfully_construct_empty_tree() {
+        let tree: RBTree<i32> = RBTree::new();
+        assert!(tree.root.is_nil());
+    }
+
+    #[test]
+    fn insert_case_1_left() {
+        let mut tree = RBTree::new();
+
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-2);
+        tree.insert(-1);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let left_right_child = (*left_child.as_ptr()).right.into_node();
+            assert_eq!((*left_right_child.as_ptr()).value, -1);
+            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_1_left_alternate() {
+        let mut tree = RBTree::new();
+
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-2);
+        tree.insert(-3);
+
+        assert_eq!(tree.in_order_vec(), vec![-3, -2, 0, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let left_left_child = (*left_child.as_ptr()).left.into_node();
+            assert_eq!((*left_left_child.as_ptr()).value, -3);
+            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_1_right() {
+        let mut tree = RBTree::new();
+
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-2);
+        tree.insert(1);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let right_left_child = (*right_child.as_ptr()).left.into_node();
+            assert_eq!((*right_left_child.as_ptr()).value, 1);
+            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_1_right_alternate() {
+        let mut tree = RBTree::new();
+
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-2);
+        tree.insert(3);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, 0, 2, 3]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let right_right_child = (*right_child.as_ptr()).right.into_node();
+            assert_eq!((*right_right_child.as_ptr()).value, 3);
+            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_2_then_3_left() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-3);
+        tree.insert(-1);
+        tree.insert(-2);
+
+        assert_eq!(tree.in_order_vec(), vec![-3, -2, -1, 0, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let left_left_child = (*left_child.as_ptr()).left.into_node();
+            assert_eq!((*left_left_child.as_ptr()).value, -3);
+            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
+            let left_right_child = (*left_child.as_ptr()).right.into_node();
+            assert_eq!((*left_right_child.as_ptr()).value, -1);
+            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_2_then_3_left_at_root() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(-2);
+        tree.insert(-1);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, -1);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Red);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 0);
+            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_2_then_3_right() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(3);
+        tree.insert(-2);
+        tree.insert(1);
+        tree.insert(2);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2, 3]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let right_left_child = (*right_child.as_ptr()).left.into_node();
+            assert_eq!((*right_left_child.as_ptr()).value, 1);
+            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
+            let right_right_child = (*right_child.as_ptr()).right.into_node();
+            assert_eq!((*right_right_child.as_ptr()).value, 3);
+            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_2_then_3_right_at_root() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(1);
+
+        assert_eq!(tree.in_order_vec(), vec![0, 1, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 1);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, 0);
+            assert_eq!((*left_child.as_ptr()).color, Color::Red);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_3_left() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(-2);
+        tree.insert(3);
+        tree.insert(2);
+        tree.insert(1);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2, 3]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let right_left_child = (*right_child.as_ptr()).left.into_node();
+            assert_eq!((*right_left_child.as_ptr()).value, 1);
+            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
+            let right_right_child = (*right_child.as_ptr()).right.into_node();
+            assert_eq!((*right_right_child.as_ptr()).value, 3);
+            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_3_left_at_root() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(-1);
+        tree.insert(-2);
+
+        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, -1);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Red);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 0);
+            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_3_right() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(2);
+        tree.insert(-3);
+        tree.insert(-2);
+        tree.insert(-1);
+
+        assert_eq!(tree.in_order_vec(), vec![-3, -2, -1, 0, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 0);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, -2);
+            assert_eq!((*left_child.as_ptr()).color, Color::Black);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Black);
+            let left_left_child = (*left_child.as_ptr()).left.into_node();
+            assert_eq!((*left_left_child.as_ptr()).value, -3);
+            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
+            let left_right_child = (*left_child.as_ptr()).right.into_node();
+            assert_eq!((*left_right_child.as_ptr()).value, -1);
+            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn insert_case_3_right_at_root() {
+        let mut tree = RBTree::new();
+        tree.insert(0);
+        tree.insert(1);
+        tree.insert(2);
+
+        assert_eq!(tree.in_order_vec(), vec![0, 1, 2]);
+
+        let root = tree.root.into_node();
+        unsafe {
+            assert_eq!((*root.as_ptr()).value, 1);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+            let left_child = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left_child.as_ptr()).value, 0);
+            assert_eq!((*left_child.as_ptr()).color, Color::Red);
+            let right_child = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right_child.as_ptr()).value, 2);
+            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn min_and_max_height() {
+        use super::Link;
+        unsafe {
+            let nil = Link::<i32>::Nil;
+            let nodes = Node::new(0, nil, nil);
+            let left = Link::Real(Node::new(0, Link::Real(nodes), nil));
+            let right = Link::Real(Node::new(0, Link::Real(nodes), nil));
+            (*nodes.as_ptr()).left = left;
+            (*nodes.as_ptr()).right = right;
+
+            let left_left = Link::Real(Node::new(0, left, nil));
+            (*left.into_node().as_ptr()).left = left_left;
+            let left_left_left = Link::Real(Node::new(0, left_left, nil));
+            (*left_left.into_node().as_ptr()).left = left_left_left;
+
+            assert_eq!(nodes.as_ref().height(), 4);
+            assert_eq!(nodes.as_ref().min_height(), 2);
+
+            Node::node_drop(nodes);
+        }
+    }
+
+    #[test]
+    fn insertion_blackbox() {
+        use rand::prelude::*;
+
+        let mut tree = RBTree::<i32>::new();
+
+        let mut rng = rand::rng();
+
+        let items: Vec<i32> = (0..50).map(|_| rng.random_range(-100..100)).collect();
+
+        for item in items {
+            tree.insert(item);
+        }
+
+        let tree_vec = tree.in_order_vec();
+        assert!(tree_vec.is_sorted());
+
+        let max_height = unsafe { tree.root.into_node().as_ref().height() };
+        let min_height = unsafe { tree.root.into_node().as_ref().min_height() };
+
+        assert!((max_height as f32 / min_height as f32) < 2.0);
+    }
+
+    #[test]
+    fn delete_root() {
+        let mut tree = RBTree::new();
+
+        tree.insert(0);
+        tree.delete(&0);
+
+        assert!(tree.root.is_nil());
+    }
+
+    #[test]
+    fn delete_case_1_then_2_left() {
+        let mut tree = RBTree::new();
+
+        tree.insert(-7);
+        tree.insert(-10);
+        tree.insert(-4);
+        tree.insert(-6);
+        tree.insert(6);
+        tree.insert(2);
+
+        assert!(tree.delete(&-10));
+        assert!(tree.in_order_vec().is_sorted());
+
+        unsafe {
+            let root = tree.root.into_node();
+            assert_eq!((*root.as_ptr()).value, -4);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -7);
+            assert_eq!((*left.as_ptr()).color, Color::Black);
+
+            let left_right = (*left.as_ptr()).right.into_node();
+            assert_eq!((*left_right.as_ptr()).value, -6);
+            assert_eq!((*left_right.as_ptr()).color, Color::Red);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 6);
+            assert_eq!((*right.as_ptr()).color, Color::Black);
+
+            let right_left = (*right.as_ptr()).left.into_node();
+            assert_eq!((*right_left.as_ptr()).value, 2);
+            assert_eq!((*right_left.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn delete_case_1_then_2_right() {
+        let mut tree = RBTree::new();
+
+        tree.insert(7);
+        tree.insert(10);
+        tree.insert(4);
+        tree.insert(6);
+        tree.insert(-6);
+        tree.insert(-2);
+
+        assert!(tree.delete(&10));
+        assert!(tree.in_order_vec().is_sorted());
+
+        unsafe {
+            let root = tree.root.into_node();
+            assert_eq!((*root.as_ptr()).value, 4);
+            assert_eq!((*root.as_ptr()).color, Color::Black);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -6);
+            assert_eq!((*left.as_ptr()).color, Color::Black);
+
+            let left_right = (*left.as_ptr()).right.into_node();
+            assert_eq!((*left_right.as_ptr()).value, -2);
+            assert_eq!((*left_right.as_ptr()).color, Color::Red);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 7);
+            assert_eq!((*right.as_ptr()).color, Color::Black);
+
+            let right_left = (*right.as_ptr()).left.into_node();
+            assert_eq!((*right_left.as_ptr()).value, 6);
+            assert_eq!((*right_left.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn delete_case_3_then_4_left() {
+        let mut tree = RBTree::new();
+
+        let vals = vec![0, -10, 10, -12, 5, 3, 8];
+
+        for val in vals {
+            tree.insert(val);
+        }
+
+        assert!(tree.delete(&3));
 
-        let root = tree.root.into_node();
         unsafe {
+            let root = tree.root.into_node();
             assert_eq!((*root.as_ptr()).value, 0);
             assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let right_right_child = (*right_child.as_ptr()).right.into_node();
-            assert_eq!((*right_right_child.as_ptr()).value, 3);
-            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -10);
+            assert_eq!((*left.as_ptr()).color, Color::Black);
+
+            let left_left = (*left.as_ptr()).left.into_node();
+            assert_eq!((*left_left.as_ptr()).value, -12);
+            assert_eq!((*left_left.as_ptr()).color, Color::Red);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 8);
+            assert_eq!((*right.as_ptr()).color, Color::Red);
+
+            let right_left = (*right.as_ptr()).left.into_node();
+            assert_eq!((*right_left.as_ptr()).value, 5);
+            assert_eq!((*right_left.as_ptr()).color, Color::Black);
+
+            let right_right = (*right.as_ptr()).right.into_node();
+            assert_eq!((*right_right.as_ptr()).value, 10);
+            assert_eq!((*right_right.as_ptr()).color, Color::Black);
         }
     }
 
     #[test]
-    fn insert_case_2_then_3_left() {
+    fn delete_case_3_then_4_right() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-3);
-        tree.insert(-1);
-        tree.insert(-2);
 
-        assert_eq!(tree.in_order_vec(), vec![-3, -2, -1, 0, 2]);
+        let vals = vec![0, 10, -10, 12, -5, -3, -8];
+
+        for val in vals {
+            tree.insert(val);
+        }
+
+        assert!(tree.delete(&-3));
 
-        let root = tree.root.into_node();
         unsafe {
+            let root = tree.root.into_node();
             assert_eq!((*root.as_ptr()).value, 0);
             assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let left_left_child = (*left_child.as_ptr()).left.into_node();
-            assert_eq!((*left_left_child.as_ptr()).value, -3);
-            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
-            let left_right_child = (*left_child.as_ptr()).right.into_node();
-            assert_eq!((*left_right_child.as_ptr()).value, -1);
-            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -8);
+            assert_eq!((*left.as_ptr()).color, Color::Red);
+
+            let left_left = (*left.as_ptr()).left.into_node();
+            assert_eq!((*left_left.as_ptr()).value, -10);
+            assert_eq!((*left_left.as_ptr()).color, Color::Black);
+
+            let left_right = (*left.as_ptr()).right.into_node();
+            assert_eq!((*left_right.as_ptr()).value, -5);
+            assert_eq!((*left_right.as_ptr()).color, Color::Black);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 10);
+            assert_eq!((*right.as_ptr()).color, Color::Black);
+
+            let right_right = (*right.as_ptr()).right.into_node();
+            assert_eq!((*right_right.as_ptr()).value, 12);
+            assert_eq!((*right_right.as_ptr()).color, Color::Red);
         }
     }
 
     #[test]
-    fn insert_case_2_then_3_left_at_root() {
+    fn delete_case_4_left() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(-2);
-        tree.insert(-1);
 
-        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0]);
+        let vals = vec![0, -10, 10, -12, 5, 3, 12];
+
+        for val in vals {
+            tree.insert(val);
+        }
+
+        assert!(tree.delete(&3));
 
-        let root = tree.root.into_node();
         unsafe {
-            assert_eq!((*root.as_ptr()).value, -1);
+            let root = tree.root.into_node();
+            assert_eq!((*root.as_ptr()).value, 0);
             assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Red);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 0);
-            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -10);
+            assert_eq!((*left.as_ptr()).color, Color::Black);
+
+            let left_left = (*left.as_ptr()).left.into_node();
+            assert_eq!((*left_left.as_ptr()).value, -12);
+            assert_eq!((*left_left.as_ptr()).color, Color::Red);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 10);
+            assert_eq!((*right.as_ptr()).color, Color::Red);
+
+            let right_left = (*right.as_ptr()).left.into_node();
+            assert_eq!((*right_left.as_ptr()).value, 5);
+            assert_eq!((*right_left.as_ptr()).color, Color::Black);
+
+            let right_right = (*right.as_ptr()).right.into_node();
+            assert_eq!((*right_right.as_ptr()).value, 12);
+            assert_eq!((*right_right.as_ptr()).color, Color::Black);
         }
     }
 
     #[test]
-    fn insert_case_2_then_3_right() {
+    fn delete_case_4_right() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(3);
-        tree.insert(-2);
-        tree.insert(1);
-        tree.insert(2);
 
-        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2, 3]);
+        let vals = vec![0, 10, -10, 12, -5, -3, -12];
+
+        for val in vals {
+            tree.insert(val);
+        }
+
+        assert!(tree.delete(&-3));
 
-        let root = tree.root.into_node();
         unsafe {
+            let root = tree.root.into_node();
             assert_eq!((*root.as_ptr()).value, 0);
             assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let right_left_child = (*right_child.as_ptr()).left.into_node();
-            assert_eq!((*right_left_child.as_ptr()).value, 1);
-            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
-            let right_right_child = (*right_child.as_ptr()).right.into_node();
-            assert_eq!((*right_right_child.as_ptr()).value, 3);
-            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+
+            let left = (*root.as_ptr()).left.into_node();
+            assert_eq!((*left.as_ptr()).value, -10);
+            assert_eq!((*left.as_ptr()).color, Color::Red);
+
+            let left_left = (*left.as_ptr()).left.into_node();
+            assert_eq!((*left_left.as_ptr()).value, -12);
+            assert_eq!((*left_left.as_ptr()).color, Color::Black);
+
+            let left_right = (*left.as_ptr()).right.into_node();
+            assert_eq!((*left_right.as_ptr()).value, -5);
+            assert_eq!((*left_right.as_ptr()).color, Color::Black);
+
+            let right = (*root.as_ptr()).right.into_node();
+            assert_eq!((*right.as_ptr()).value, 10);
+            assert_eq!((*right.as_ptr()).color, Color::Black);
+
+            let right_right = (*right.as_ptr()).right.into_node();
+            assert_eq!((*right_right.as_ptr()).value, 12);
+            assert_eq!((*right_right.as_ptr()).color, Color::Red);
+        }
+    }
+
+    #[test]
+    fn deletion_blackbox() {
+        use rand::prelude::*;
+
+        let mut tree = RBTree::<i32>::new();
+
+        let mut rng = rand::rng();
+
+        let items: Vec<i32> = (0..50).map(|_| rng.random_range(-100..100)).collect();
+
+        for item in &items {
+            tree.insert(*item);
+        }
+
+        let indices: Vec<usize> = (0..10).map(|_| rng.random_range(0..items.len())).collect();
+
+        for index in indices {
+            tree.delete(&items[index]);
         }
+
+        let tree_vec = tree.in_order_vec();
+        assert!(tree_vec.is_sorted());
+
+        let max_height = unsafe { tree.root.into_node().as_ref().height() };
+        let min_height = unsafe { tree.root.into_node().as_ref().min_height() };
+
+        assert!((max_height as f32 / min_height as f32) < 2.0);
+    }
+
+    #[test]
+    fn simple_fuzzy_search() {
+        let mut tree: RBTree<i32> = RBTree::new();
+        tree.insert(1);
+        tree.insert(2);
+        tree.insert(5);
+        tree.insert(0);
+
+        assert_eq!(tree.get_nearest(&-1), Some(&0));
+    }
+
+    #[test]
+    fn complex_fuzzy_search() {
+        let mut tree: RBTree<i32> = RBTree::new();
+        tree.insert(1);
+        tree.insert(2);
+        tree.insert(5);
+        tree.insert(0);
+        tree.insert(-4);
+        tree.insert(7);
+        tree.insert(12);
+        tree.insert(-3);
+
+        tree.print();
+        assert_eq!(tree.get_nearest(&8), Some(&7));
+    }
+
+    #[test]
+    fn fuzzy_search_returns_nothing() {
+        let tree: RBTree<i32> = RBTree::new();
+
+        assert_eq!(tree.get_nearest(&10), None);
+    }
+
+    #[test]
+    fn fuzzy_search_adjusts_for_closer_item() {
+        let mut tree: RBTree<i32> = RBTree::new();
+
+        tree.insert(0);
+        tree.insert(5);
+        tree.insert(-1);
+
+        assert_eq!(tree.get_nearest(&4), Some(&5));
     }
 
     #[test]
-    fn insert_case_2_then_3_right_at_root() {
+    fn iter_walks_in_order() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(1);
+        let vals = vec![5, -3, 12, 0, 7, 7, 99, -40, 2];
 
-        assert_eq!(tree.in_order_vec(), vec![0, 1, 2]);
-
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 1);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, 0);
-            assert_eq!((*left_child.as_ptr()).color, Color::Red);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        for val in &vals {
+            tree.insert(*val);
         }
+
+        let mut sorted = vals.clone();
+        sorted.sort();
+
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), sorted);
+        assert_eq!((&tree).into_iter().count(), vals.len());
+        assert_eq!(tree.iter().len(), vals.len());
     }
 
     #[test]
-    fn insert_case_3_left() {
+    fn iter_is_double_ended() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(-2);
-        tree.insert(3);
-        tree.insert(2);
-        tree.insert(1);
 
-        assert_eq!(tree.in_order_vec(), vec![-2, 0, 1, 2, 3]);
-
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let right_left_child = (*right_child.as_ptr()).left.into_node();
-            assert_eq!((*right_left_child.as_ptr()).value, 1);
-            assert_eq!((*right_left_child.as_ptr()).color, Color::Red);
-            let right_right_child = (*right_child.as_ptr()).right.into_node();
-            assert_eq!((*right_right_child.as_ptr()).value, 3);
-            assert_eq!((*right_right_child.as_ptr()).color, Color::Red);
+        for val in 0..10 {
+            tree.insert(val);
         }
+
+        assert_eq!(
+            tree.iter().rev().copied().collect::<Vec<_>>(),
+            (0..10).rev().collect::<Vec<_>>()
+        );
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&9));
+        assert_eq!(iter.next_back(), Some(&8));
+        assert_eq!(iter.len(), 7);
+        assert_eq!(
+            iter.copied().collect::<Vec<_>>(),
+            (1..8).collect::<Vec<_>>()
+        );
     }
 
     #[test]
-    fn insert_case_3_left_at_root() {
+    fn iter_on_empty_tree() {
+        let tree: RBTree<i32> = RBTree::new();
+
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.iter().next_back(), None);
+        assert_eq!(tree.into_iter().next(), None);
+    }
+
+    #[test]
+    fn into_iter_moves_elements_out() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(-1);
-        tree.insert(-2);
 
-        assert_eq!(tree.in_order_vec(), vec![-2, -1, 0]);
+        for word in ["pear", "apple", "fig", "banana"] {
+            tree.insert(String::from(word));
+        }
+        tree.delete(&String::from("fig"));
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, -1);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Red);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 0);
-            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        let owned: Vec<String> = tree.into_iter().collect();
+        assert_eq!(owned, vec!["apple", "banana", "pear"]);
+    }
+
+    #[test]
+    fn into_iter_from_both_ends() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
+        let tree: RBTree<i32> = (0..1000).collect();
+        let mut expected: std::collections::VecDeque<i32> = (0..1000).collect();
+
+        let mut iter = tree.into_iter();
+        while !expected.is_empty() {
+            assert_eq!(iter.len(), expected.len());
+            if rng.random_bool(0.5) {
+                assert_eq!(iter.next(), expected.pop_front());
+            } else {
+                assert_eq!(iter.next_back(), expected.pop_back());
+            }
         }
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
     }
 
     #[test]
-    fn insert_case_3_right() {
+    fn range_with_every_bound_kind() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(2);
-        tree.insert(-3);
-        tree.insert(-2);
-        tree.insert(-1);
-
-        assert_eq!(tree.in_order_vec(), vec![-3, -2, -1, 0, 2]);
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, -2);
-            assert_eq!((*left_child.as_ptr()).color, Color::Black);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Black);
-            let left_left_child = (*left_child.as_ptr()).left.into_node();
-            assert_eq!((*left_left_child.as_ptr()).value, -3);
-            assert_eq!((*left_left_child.as_ptr()).color, Color::Red);
-            let left_right_child = (*left_child.as_ptr()).right.into_node();
-            assert_eq!((*left_right_child.as_ptr()).value, -1);
-            assert_eq!((*left_right_child.as_ptr()).color, Color::Red);
+        for val in (0..50).rev() {
+            tree.insert(val * 2);
         }
+
+        assert_eq!(
+            tree.range(10..20).copied().collect::<Vec<_>>(),
+            vec![10, 12, 14, 16, 18]
+        );
+        assert_eq!(
+            tree.range(11..=20).copied().collect::<Vec<_>>(),
+            vec![12, 14, 16, 18, 20]
+        );
+        assert_eq!(tree.range(..5).copied().collect::<Vec<_>>(), vec![0, 2, 4]);
+        assert_eq!(tree.range(95..).copied().collect::<Vec<_>>(), vec![96, 98]);
+        assert_eq!(tree.range(..).count(), 50);
+        assert_eq!(
+            tree.range((Bound::Excluded(10), Bound::Excluded(16)))
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![12, 14]
+        );
     }
 
     #[test]
-    fn insert_case_3_right_at_root() {
+    fn range_is_double_ended() {
         let mut tree = RBTree::new();
-        tree.insert(0);
-        tree.insert(1);
-        tree.insert(2);
-
-        assert_eq!(tree.in_order_vec(), vec![0, 1, 2]);
 
-        let root = tree.root.into_node();
-        unsafe {
-            assert_eq!((*root.as_ptr()).value, 1);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
-            let left_child = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left_child.as_ptr()).value, 0);
-            assert_eq!((*left_child.as_ptr()).color, Color::Red);
-            let right_child = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right_child.as_ptr()).value, 2);
-            assert_eq!((*right_child.as_ptr()).color, Color::Red);
+        for val in 0..20 {
+            tree.insert(val);
         }
+
+        let mut range = tree.range(5..10);
+        assert_eq!(range.next_back(), Some(&9));
+        assert_eq!(range.next(), Some(&5));
+        assert_eq!(range.rev().copied().collect::<Vec<_>>(), vec![8, 7, 6]);
     }
 
     #[test]
-    fn min_and_max_height() {
-        use super::Link;
-        unsafe {
-            let nil_node = NilNode::new();
-            let nil = Link::Nil(nil_node);
-            let nodes = Node::new(0, nil, nil);
-            let left = Link::Real(Node::new(0, Link::Real(nodes), nil));
-            let right = Link::Real(Node::new(0, Link::Real(nodes), nil));
-            (*nodes.as_ptr()).left = left;
-            (*nodes.as_ptr()).right = right;
+    fn empty_ranges() {
+        let mut tree = RBTree::new();
 
-            let left_left = Link::Real(Node::new(0, left, nil));
-            (*left.into_node().as_ptr()).left = left_left;
-            let left_left_left = Link::Real(Node::new(0, left_left, nil));
-            (*left_left.into_node().as_ptr()).left = left_left_left;
+        for val in [1, 3, 5, 7] {
+            tree.insert(val);
+        }
 
-            assert_eq!(nodes.as_ref().height(), 4);
-            assert_eq!(nodes.as_ref().min_height(), 2);
+        assert_eq!(tree.range(4..5).next(), None);
+        assert_eq!(tree.range(8..).next(), None);
+        assert_eq!(tree.range(..1).next_back(), None);
+        assert_eq!(RBTree::<i32>::new().range(..).next(), None);
+    }
 
-            Node::node_drop(nodes);
+    #[test]
+    fn floor_ceiling_predecessor_successor() {
+        let mut tree = RBTree::new();
 
-            let _ = Box::from_raw(nil_node.as_ptr());
+        for val in [10, 20, 30, 40] {
+            tree.insert(val);
         }
+
+        assert_eq!(tree.floor(&25), Some(&20));
+        assert_eq!(tree.floor(&20), Some(&20));
+        assert_eq!(tree.floor(&5), None);
+
+        assert_eq!(tree.ceiling(&25), Some(&30));
+        assert_eq!(tree.ceiling(&30), Some(&30));
+        assert_eq!(tree.ceiling(&45), None);
+
+        assert_eq!(tree.predecessor(&20), Some(&10));
+        assert_eq!(tree.predecessor(&10), None);
+
+        assert_eq!(tree.successor(&20), Some(&30));
+        assert_eq!(tree.successor(&40), None);
     }
 
     #[test]
-    fn insertion_blackbox() {
+    fn select_and_rank_match_sorted_order() {
         use rand::prelude::*;
 
         let mut tree = RBTree::<i32>::new();
-
         let mut rng = rand::rng();
+        let mut items: Vec<i32> = (0..200).map(|_| rng.random_range(-1000..1000)).collect();
 
-        let items: Vec<i32> = (0..50).map(|_| rng.random_range(-100..100)).collect();
+        for item in &items {
+            tree.insert(*item);
+        }
+        for item in items.drain(..50) {
+            tree.delete(&item);
+        }
 
-        for item in items {
-            tree.insert(item);
+        items.sort();
+        assert_eq!(tree.len(), items.len());
+
+        for (index, item) in items.iter().enumerate() {
+            assert_eq!(tree.select(index), Some(item));
+            assert_eq!(tree.rank(item), items.partition_point(|other| other < item));
         }
+        assert_eq!(tree.select(items.len()), None);
+    }
 
-        let tree_vec = tree.in_order_vec();
-        assert!(tree_vec.is_sorted());
+    #[test]
+    fn subtree_sizes_survive_rotations() {
+        let mut tree = RBTree::new();
 
-        let max_height = unsafe { tree.root.into_node().as_ref().height() };
-        let min_height = unsafe { tree.root.into_node().as_ref().min_height() };
+        for val in 0..64 {
+            tree.insert(val);
+        }
+        for val in (0..64).step_by(3) {
+            tree.delete(&val);
+        }
 
-        assert!((max_height as f32 / min_height as f32) < 2.0);
+        fn check(link: super::Link<i32>) -> usize {
+            match link {
+                super::Link::Real(node) => unsafe {
+                    let size = check((*node.as_ptr()).left) + check((*node.as_ptr()).right) + 1;
+                    assert_eq!((*node.as_ptr()).size, size);
+                    size
+                },
+                super::Link::Nil => 0,
+            }
+        }
+
+        assert_eq!(check(tree.root), tree.len());
+        assert_eq!(tree.rank(&100), tree.len());
+        assert_eq!(tree.rank(&-1), 0);
     }
 
     #[test]
-    fn delete_root() {
-        let mut tree = RBTree::new();
+    fn sum_augmentation_over_ranges() {
+        use rand::prelude::*;
 
-        tree.insert(0);
-        tree.delete(&0);
+        let mut tree = RBTree::<i32, Sum>::new_augmented();
+        let mut rng = rand::rng();
+        let mut items: Vec<i32> = (0..300).map(|_| rng.random_range(-500..500)).collect();
 
-        assert!(tree.root.is_nil());
+        for item in &items {
+            tree.insert(*item);
+        }
+        for item in items.drain(..100) {
+            tree.delete(&item);
+        }
+
+        let brute_force = |start: i32, end: i32| -> i64 {
+            items
+                .iter()
+                .filter(|item| (start..end).contains(*item))
+                .map(|item| *item as i64)
+                .sum()
+        };
+
+        assert_eq!(
+            tree.aggregate(),
+            items.iter().map(|item| *item as i64).sum()
+        );
+        for _ in 0..100 {
+            let start = rng.random_range(-600..600);
+            let end = rng.random_range(start..=600);
+            assert_eq!(tree.range_aggregate(start..end), brute_force(start, end));
+        }
     }
 
     #[test]
-    fn delete_case_1_then_2_left() {
-        let mut tree = RBTree::new();
+    fn augmentation_combines_in_order() {
+        let mut tree = RBTree::<i32, Concat>::new_augmented();
 
-        tree.insert(-7);
-        tree.insert(-10);
-        tree.insert(-4);
-        tree.insert(-6);
-        tree.insert(6);
-        tree.insert(2);
+        for val in [8, 3, 10, 1, 6, 14, 4, 7, 13, 0, 2] {
+            tree.insert(val);
+        }
+        tree.delete(&3);
+        tree.delete(&10);
 
-        assert!(tree.delete(&-10));
-        assert!(tree.in_order_vec().is_sorted());
+        assert_eq!(tree.aggregate(), tree.in_order_vec());
+        assert_eq!(tree.range_aggregate(2..=7), vec![2, 4, 6, 7]);
+        assert_eq!(tree.range_aggregate(..4), vec![0, 1, 2]);
+        assert_eq!(tree.range_aggregate(9..12), Vec::<i32>::new());
+    }
 
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, -4);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+    #[test]
+    fn clone_is_deep_and_independent() {
+        let mut tree = RBTree::<i32, Sum>::new_augmented();
+        for val in [5, 2, 8, 1, 9, 3, 7] {
+            tree.insert(val);
+        }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -7);
-            assert_eq!((*left.as_ptr()).color, Color::Black);
+        let mut cloned = tree.clone();
+        assert_eq!(cloned.in_order_vec(), tree.in_order_vec());
+        assert_eq!(cloned.aggregate(), 35);
+        unsafe {
+            assert_eq!(
+                cloned.root.into_node().as_ref().height(),
+                tree.root.into_node().as_ref().height()
+            );
+        }
 
-            let left_right = (*left.as_ptr()).right.into_node();
-            assert_eq!((*left_right.as_ptr()).value, -6);
-            assert_eq!((*left_right.as_ptr()).color, Color::Red);
+        cloned.delete(&5);
+        cloned.insert(100);
+        assert_eq!(tree.in_order_vec(), vec![1, 2, 3, 5, 7, 8, 9]);
+        assert_eq!(cloned.in_order_vec(), vec![1, 2, 3, 7, 8, 9, 100]);
+        assert_eq!(cloned.select(6), Some(&100));
+    }
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 6);
-            assert_eq!((*right.as_ptr()).color, Color::Black);
+    #[test]
+    fn nearest_checks_the_other_subtree() {
+        let mut tree: RBTree<i32> = RBTree::new();
 
-            let right_left = (*right.as_ptr()).left.into_node();
-            assert_eq!((*right_left.as_ptr()).value, 2);
-            assert_eq!((*right_left.as_ptr()).color, Color::Red);
+        for val in [50, 20, 80, 10, 30, 70, 90, 29, 31] {
+            tree.insert(val);
         }
+
+        assert_eq!(tree.get_nearest(&49), Some(&50));
+        assert_eq!(tree.get_nearest(&39), Some(&31));
+        assert_eq!(tree.nearest(&39), Some(&31));
+        assert_eq!(tree.nearest(&60), Some(&50));
+        assert_eq!(tree.nearest(&1000), Some(&90));
+        assert_eq!(tree.nearest(&-1000), Some(&10));
     }
 
     #[test]
-    fn delete_case_1_then_2_right() {
-        let mut tree = RBTree::new();
+    fn nearest_matches_brute_force() {
+        use rand::prelude::*;
 
-        tree.insert(7);
-        tree.insert(10);
-        tree.insert(4);
-        tree.insert(6);
-        tree.insert(-6);
-        tree.insert(-2);
+        let mut tree: RBTree<i64> = RBTree::new();
+        let mut rng = rand::rng();
+        let items: Vec<i64> = (0..300)
+            .map(|_| rng.random_range(-10_000..10_000))
+            .collect();
 
-        assert!(tree.delete(&10));
-        assert!(tree.in_order_vec().is_sorted());
+        for item in &items {
+            tree.insert(*item);
+        }
 
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, 4);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+        for _ in 0..200 {
+            let target = rng.random_range(-11_000..11_000);
+            let best = items.iter().map(|item| item.abs_diff(target)).min();
+            assert_eq!(
+                tree.nearest(&target).map(|item| item.abs_diff(target)),
+                best
+            );
+            assert_eq!(
+                tree.get_nearest(&target).map(|item| item.abs_diff(target)),
+                best
+            );
+        }
+    }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -6);
-            assert_eq!((*left.as_ptr()).color, Color::Black);
+    #[test]
+    fn nearest_by_closure_on_wrapped_floats() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Seconds(f64);
 
-            let left_right = (*left.as_ptr()).right.into_node();
-            assert_eq!((*left_right.as_ptr()).value, -2);
-            assert_eq!((*left_right.as_ptr()).color, Color::Red);
+        impl Eq for Seconds {}
+
+        impl PartialOrd for Seconds {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 7);
-            assert_eq!((*right.as_ptr()).color, Color::Black);
+        impl Ord for Seconds {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
 
-            let right_left = (*right.as_ptr()).left.into_node();
-            assert_eq!((*right_left.as_ptr()).value, 6);
-            assert_eq!((*right_left.as_ptr()).color, Color::Red);
+        let mut tree = RBTree::new();
+        for time in [0.5, 1.25, 3.0, 7.75] {
+            tree.insert(Seconds(time));
         }
+
+        let distance = |lhs: &Seconds, rhs: &Seconds| (lhs.0 - rhs.0).abs();
+        assert_eq!(
+            tree.nearest_by(&Seconds(2.0), distance),
+            Some(&Seconds(1.25))
+        );
+        assert_eq!(
+            tree.k_nearest_by(&Seconds(2.0), 3, distance),
+            vec![&Seconds(1.25), &Seconds(3.0), &Seconds(0.5)]
+        );
     }
 
     #[test]
-    fn delete_case_3_then_4_left() {
-        let mut tree = RBTree::new();
+    fn k_nearest_walks_both_directions() {
+        let mut tree: RBTree<u32> = RBTree::new();
 
-        let vals = vec![0, -10, 10, -12, 5, 3, 8];
+        for val in [1, 4, 6, 9, 15, 16, 30] {
+            tree.insert(val);
+        }
 
-        for val in vals {
+        assert_eq!(tree.k_nearest(&10, 3), vec![&9, &6, &15]);
+        assert_eq!(tree.k_nearest(&6, 2), vec![&6, &4]);
+        assert_eq!(tree.k_nearest(&0, 2), vec![&1, &4]);
+        assert_eq!(tree.k_nearest(&100, 100).len(), 7);
+        assert!(RBTree::<u32>::new().k_nearest(&1, 3).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn reverse_order_comparator() {
+        let mut tree = RBTree::with_comparator(|lhs: &i32, rhs: &i32| rhs.cmp(lhs));
+
+        for val in [3, 9, -2, 7, 0] {
             tree.insert(val);
         }
 
+        assert_eq!(tree.in_order_vec(), vec![9, 7, 3, 0, -2]);
+        assert!(tree.search(&7));
         assert!(tree.delete(&3));
+        assert!(!tree.delete(&3));
+        assert_eq!(tree.floor(&5), Some(&7));
+        assert_eq!(tree.ceiling(&5), Some(&0));
+        assert_eq!(tree.range(8..=-1).copied().collect::<Vec<_>>(), vec![7, 0]);
+        assert_eq!(tree.rank(&0), 2);
+    }
 
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+    #[test]
+    fn key_extraction_without_ord() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Player {
+            name: &'static str,
+            score: f32,
+        }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -10);
-            assert_eq!((*left.as_ptr()).color, Color::Black);
+        let mut leaderboard = RBTree::by_key(|player: &Player| (player.score * 100.0) as i64);
+        leaderboard.insert(Player {
+            name: "ada",
+            score: 12.5,
+        });
+        leaderboard.insert(Player {
+            name: "bob",
+            score: 3.25,
+        });
+        leaderboard.insert(Player {
+            name: "cyd",
+            score: 40.0,
+        });
+
+        let names: Vec<&str> = leaderboard.iter().map(|player| player.name).collect();
+        assert_eq!(names, vec!["bob", "ada", "cyd"]);
+
+        let probe = Player {
+            name: "",
+            score: 12.5,
+        };
+        assert!(leaderboard.search(&probe));
+        assert_eq!(leaderboard.select(2).map(|player| player.name), Some("cyd"));
+    }
 
-            let left_left = (*left.as_ptr()).left.into_node();
-            assert_eq!((*left_left.as_ptr()).value, -12);
-            assert_eq!((*left_left.as_ptr()).color, Color::Red);
+    #[test]
+    fn case_insensitive_strings() {
+        let mut tree = RBTree::with_comparator(ByKey(|word: &String| word.to_lowercase()));
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 8);
-            assert_eq!((*right.as_ptr()).color, Color::Red);
+        for word in ["banana", "Apple", "cherry"] {
+            tree.insert(String::from(word));
+        }
 
-            let right_left = (*right.as_ptr()).left.into_node();
-            assert_eq!((*right_left.as_ptr()).value, 5);
-            assert_eq!((*right_left.as_ptr()).color, Color::Black);
+        assert_eq!(tree.in_order_vec(), vec!["Apple", "banana", "cherry"]);
+        assert!(tree.search(&String::from("BANANA")));
+    }
 
-            let right_right = (*right.as_ptr()).right.into_node();
-            assert_eq!((*right_right.as_ptr()).value, 10);
-            assert_eq!((*right_right.as_ptr()).color, Color::Black);
-        }
+    #[test]
+    fn borrowed_lookups() {
+        let mut tree: RBTree<String> = ["pear", "fig", "apple", "kiwi", "date"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let shared = &tree;
+        assert!(shared.contains("fig"));
+        assert!(!shared.contains("grape"));
+        assert_eq!(shared.get("kiwi").map(String::as_str), Some("kiwi"));
+        assert_eq!(shared.first().map(String::as_str), Some("apple"));
+        assert_eq!(shared.last().map(String::as_str), Some("pear"));
+
+        assert_eq!(tree.take("fig"), Some(String::from("fig")));
+        assert_eq!(tree.take("fig"), None);
+        assert_eq!(tree.pop_first(), Some(String::from("apple")));
+        assert_eq!(tree.pop_last(), Some(String::from("pear")));
+        assert_eq!(tree.in_order_vec(), vec!["date", "kiwi"]);
+        tree.validate().unwrap();
+
+        tree.pop_first();
+        tree.pop_last();
+        assert_eq!(tree.pop_first(), None);
+        assert_eq!(tree.first(), None);
     }
 
     #[test]
-    fn delete_case_3_then_4_right() {
+    fn split_off_and_join() {
         let mut tree = RBTree::new();
-
-        let vals = vec![0, 10, -10, 12, -5, -3, -8];
-
-        for val in vals {
+        for val in 0..200 {
             tree.insert(val);
         }
 
-        assert!(tree.delete(&-3));
-
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+        let upper = tree.split_off(&120);
+        tree.validate().unwrap();
+        upper.validate().unwrap();
+        assert_eq!(tree.in_order_vec(), (0..120).collect::<Vec<_>>());
+        assert_eq!(upper.in_order_vec(), (120..200).collect::<Vec<_>>());
+        assert_eq!(upper.select(0), Some(&120));
+
+        let mut small = RBTree::new();
+        small.insert(-5);
+        let joined = RBTree::join(RBTree::new(), -10, RBTree::join(small, -1, tree));
+        joined.validate().unwrap();
+        assert_eq!(joined.len(), 123);
+        assert_eq!(joined.in_order_vec()[..4], [-10, -5, -1, 0]);
+
+        let mut empty = RBTree::<i32>::new();
+        assert!(empty.split_off(&3).is_empty());
+    }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -8);
-            assert_eq!((*left.as_ptr()).color, Color::Red);
+    #[test]
+    #[should_panic]
+    fn join_rejects_out_of_order_pivot() {
+        let mut left = RBTree::new();
+        left.insert(10);
+        let _ = RBTree::join(left, 5, RBTree::new());
+    }
 
-            let left_left = (*left.as_ptr()).left.into_node();
-            assert_eq!((*left_left.as_ptr()).value, -10);
-            assert_eq!((*left_left.as_ptr()).color, Color::Black);
+    #[test]
+    fn set_operations_match_btree_set() {
+        use rand::prelude::*;
+        use std::collections::BTreeSet;
 
-            let left_right = (*left.as_ptr()).right.into_node();
-            assert_eq!((*left_right.as_ptr()).value, -5);
-            assert_eq!((*left_right.as_ptr()).color, Color::Black);
+        let mut rng = rand::rng();
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 10);
-            assert_eq!((*right.as_ptr()).color, Color::Black);
+        for (left_len, right_len) in [(0, 10), (300, 5), (7, 400), (250, 250)] {
+            let left: BTreeSet<i32> = (0..left_len).map(|_| rng.random_range(0..600)).collect();
+            let right: BTreeSet<i32> = (0..right_len).map(|_| rng.random_range(0..600)).collect();
+            let build = |set: &BTreeSet<i32>| {
+                let mut tree = RBTree::new();
+                for val in set {
+                    tree.insert(*val);
+                }
+                tree
+            };
 
-            let right_right = (*right.as_ptr()).right.into_node();
-            assert_eq!((*right_right.as_ptr()).value, 12);
-            assert_eq!((*right_right.as_ptr()).color, Color::Red);
+            let union = build(&left).union(build(&right));
+            union.validate().unwrap();
+            assert_eq!(
+                union.in_order_vec(),
+                left.union(&right).copied().collect::<Vec<_>>()
+            );
+
+            let intersection = build(&left).intersection(build(&right));
+            intersection.validate().unwrap();
+            assert_eq!(
+                intersection.in_order_vec(),
+                left.intersection(&right).copied().collect::<Vec<_>>()
+            );
+
+            let difference = build(&left).difference(build(&right));
+            difference.validate().unwrap();
+            assert_eq!(
+                difference.in_order_vec(),
+                left.difference(&right).copied().collect::<Vec<_>>()
+            );
+
+            let mut lower = build(&left);
+            let split_point = rng.random_range(0..600);
+            let upper = lower.split_off(&split_point);
+            lower.validate().unwrap();
+            upper.validate().unwrap();
+            assert!(lower.iter().all(|val| *val < split_point));
+            assert!(upper.iter().all(|val| *val >= split_point));
+            assert_eq!(lower.len() + upper.len(), left.len());
         }
     }
 
     #[test]
-    fn delete_case_4_left() {
-        let mut tree = RBTree::new();
-
-        let vals = vec![0, -10, 10, -12, 5, 3, 12];
-
-        for val in vals {
-            tree.insert(val);
+    fn bulk_build_from_sorted() {
+        for len in 0..130 {
+            let tree: RBTree<i32> = RBTree::from_sorted_iter(0..len);
+            tree.validate().unwrap();
+            assert_eq!(tree.in_order_vec(), (0..len).collect::<Vec<_>>());
         }
 
-        assert!(tree.delete(&3));
+        let tree = RBTree::<i32, Sum>::from_sorted_iter([1, 1, 2, 3, 5, 8, 8]);
+        assert_eq!(tree.aggregate(), 28);
+        assert_eq!(tree.range_aggregate(2..8), 10);
+        assert_eq!(tree.rank(&8), 5);
+    }
 
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+    #[test]
+    #[should_panic]
+    fn bulk_build_rejects_unsorted_input() {
+        let _: RBTree<i32> = RBTree::from_sorted_iter([1, 3, 2]);
+    }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -10);
-            assert_eq!((*left.as_ptr()).color, Color::Black);
+    #[test]
+    fn collect_and_extend() {
+        use rand::prelude::*;
 
-            let left_left = (*left.as_ptr()).left.into_node();
-            assert_eq!((*left_left.as_ptr()).value, -12);
-            assert_eq!((*left_left.as_ptr()).color, Color::Red);
+        let mut rng = rand::rng();
+        let mut values: Vec<i32> = (0..500).map(|_| rng.random_range(0..100)).collect();
+
+        let mut tree: RBTree<i32> = values.iter().copied().collect();
+        tree.validate().unwrap();
+        tree.extend([-1, 200, 50]);
+        tree.extend(Vec::new());
+        tree.validate().unwrap();
+
+        values.extend([-1, 200, 50]);
+        values.sort();
+        assert_eq!(tree.in_order_vec(), values);
+
+        let mut reversed = RBTree::with_comparator(|lhs: &i32, rhs: &i32| rhs.cmp(lhs));
+        reversed.extend(0..100);
+        assert_eq!(reversed.select(0), Some(&99));
+        assert_eq!(reversed.len(), 100);
+    }
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 10);
-            assert_eq!((*right.as_ptr()).color, Color::Red);
+    #[test]
+    fn cursor_navigation() {
+        let tree: RBTree<i32> = (0..10).map(|val| val * 2).collect();
+
+        let mut cursor = tree.lower_bound(&5);
+        assert_eq!(cursor.current(), Some(&6));
+        assert_eq!(cursor.peek_prev(), Some(&4));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&2));
+
+        assert_eq!(tree.lower_bound(&6).current(), Some(&6));
+        assert_eq!(tree.upper_bound(&6).current(), Some(&8));
+
+        let mut ghost = tree.upper_bound(&18);
+        assert_eq!(ghost.current(), None);
+        assert_eq!(ghost.peek_next(), Some(&0));
+        assert_eq!(ghost.peek_prev(), Some(&18));
+        ghost.move_prev();
+        assert_eq!(ghost.current(), Some(&18));
+        ghost.move_next();
+        ghost.move_next();
+        assert_eq!(ghost.current(), Some(&0));
+    }
 
-            let right_left = (*right.as_ptr()).left.into_node();
-            assert_eq!((*right_left.as_ptr()).value, 5);
-            assert_eq!((*right_left.as_ptr()).color, Color::Black);
+    #[test]
+    fn cursor_removes_while_sweeping() {
+        let mut tree: RBTree<i32> = (0..100).collect();
 
-            let right_right = (*right.as_ptr()).right.into_node();
-            assert_eq!((*right_right.as_ptr()).value, 12);
-            assert_eq!((*right_right.as_ptr()).color, Color::Black);
+        let mut cursor = tree.lower_bound_mut(&10);
+        while let Some(&current) = cursor.current() {
+            if current % 3 == 0 {
+                assert_eq!(cursor.remove_current(), Some(current));
+            } else {
+                cursor.move_next();
+            }
         }
+        assert_eq!(cursor.remove_current(), None);
+
+        tree.validate().unwrap();
+        let expected: Vec<i32> = (0..100).filter(|val| *val < 10 || val % 3 != 0).collect();
+        assert_eq!(tree.in_order_vec(), expected);
     }
 
     #[test]
-    fn delete_case_4_right() {
+    fn cursor_inserts_next_to_current() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
         let mut tree = RBTree::new();
+        let mut values = Vec::new();
+
+        for _ in 0..500 {
+            let target = rng.random_range(0..1000);
+            let mut cursor = tree.lower_bound_mut(&target);
+            let upper = cursor.current().copied().unwrap_or(1000);
+            let lower = cursor.peek_prev().copied().unwrap_or(0);
+            let element = rng.random_range(lower..=upper);
+
+            if rng.random_bool(0.5) {
+                cursor.insert_before(element);
+                assert_eq!(cursor.peek_prev(), Some(&element));
+            } else {
+                cursor.move_prev();
+                cursor.insert_after(element);
+                assert_eq!(cursor.peek_next(), Some(&element));
+            }
+            values.push(element);
+        }
 
-        let vals = vec![0, 10, -10, 12, -5, -3, -12];
+        values.sort();
+        tree.validate().unwrap();
+        assert_eq!(tree.in_order_vec(), values);
+    }
 
-        for val in vals {
-            tree.insert(val);
-        }
+    #[test]
+    #[should_panic]
+    fn cursor_rejects_out_of_order_insert() {
+        let mut tree: RBTree<i32> = [1, 5, 9].into_iter().collect();
+        tree.lower_bound_mut(&5).insert_after(10);
+    }
 
-        assert!(tree.delete(&-3));
+    #[test]
+    fn handles_follow_their_elements() {
+        use rand::prelude::*;
 
-        unsafe {
-            let root = tree.root.into_node();
-            assert_eq!((*root.as_ptr()).value, 0);
-            assert_eq!((*root.as_ptr()).color, Color::Black);
+        let mut rng = rand::rng();
+        let mut tree = RBTree::new();
+        let mut handles: Vec<_> = (0..300)
+            .map(|val| (val, tree.insert_with_handle(val)))
+            .collect();
+        handles.shuffle(&mut rng);
+
+        // Removing by value moves successors between nodes, which the handles must follow
+        let (removed, kept) = handles.split_at(150);
+        for (val, _) in removed {
+            assert!(tree.delete(val));
+        }
+        for (_, handle) in removed {
+            assert_eq!(tree.get_by_handle(*handle), Err(HandleError::Stale));
+            assert_eq!(tree.remove_by_handle(*handle), Err(HandleError::Stale));
+            assert_eq!(tree.neighbors(*handle), Err(HandleError::Stale));
+        }
+        for (val, handle) in kept {
+            assert_eq!(tree.get_by_handle(*handle), Ok(val));
+            let (prev, next) = tree.neighbors(*handle).unwrap();
+            assert_eq!(prev, tree.predecessor(val));
+            assert_eq!(next, tree.successor(val));
+        }
 
-            let left = (*root.as_ptr()).left.into_node();
-            assert_eq!((*left.as_ptr()).value, -10);
-            assert_eq!((*left.as_ptr()).color, Color::Red);
+        for (val, handle) in kept {
+            assert_eq!(tree.remove_by_handle(*handle), Ok(*val));
+        }
+        assert!(tree.is_empty());
 
-            let left_left = (*left.as_ptr()).left.into_node();
-            assert_eq!((*left_left.as_ptr()).value, -12);
-            assert_eq!((*left_left.as_ptr()).color, Color::Black);
+        // Reused slots must not revive old handles
+        let reused = tree.insert_with_handle(7);
+        assert_eq!(tree.get_by_handle(removed[0].1), Err(HandleError::Stale));
+        assert_eq!(tree.get_by_handle(reused), Ok(&7));
+    }
 
-            let left_right = (*left.as_ptr()).right.into_node();
-            assert_eq!((*left_right.as_ptr()).value, -5);
-            assert_eq!((*left_right.as_ptr()).color, Color::Black);
+    #[test]
+    fn handles_are_tied_to_their_tree() {
+        let mut tree = RBTree::new();
+        let handle = tree.insert_with_handle(1);
+        let copy = tree.clone();
+        assert_eq!(copy.get_by_handle(handle), Err(HandleError::WrongTree));
 
-            let right = (*root.as_ptr()).right.into_node();
-            assert_eq!((*right.as_ptr()).value, 10);
-            assert_eq!((*right.as_ptr()).color, Color::Black);
+        let mut other = RBTree::new();
+        other.insert(1);
+        assert_eq!(other.get_by_handle(handle), Err(HandleError::WrongTree));
+    }
 
-            let right_right = (*right.as_ptr()).right.into_node();
-            assert_eq!((*right_right.as_ptr()).value, 12);
-            assert_eq!((*right_right.as_ptr()).color, Color::Red);
+    #[test]
+    fn handles_survive_split_and_set_operations() {
+        let mut tree = RBTree::new();
+        let handles: Vec<_> = (0..100).map(|val| tree.insert_with_handle(val)).collect();
+
+        let upper = tree.split_off(&60);
+        assert_eq!(tree.get_by_handle(handles[10]), Ok(&10));
+        assert_eq!(tree.get_by_handle(handles[70]), Err(HandleError::Stale));
+        assert_eq!(
+            upper.get_by_handle(handles[70]),
+            Err(HandleError::WrongTree)
+        );
+
+        let mut others = RBTree::new();
+        for val in (0..200).step_by(5) {
+            others.insert(val);
         }
+        let mut union = tree.union(others);
+        assert_eq!(union.get_by_handle(handles[10]), Ok(&10));
+        assert_eq!(union.remove_by_handle(handles[11]), Ok(11));
+        assert_eq!(union.len(), 87);
+
+        // The larger side of a join keeps its handles
+        let joined = RBTree::join(union, 500, RBTree::from_sorted_iter(501..520));
+        assert_eq!(joined.get_by_handle(handles[12]), Ok(&12));
+        joined.validate().unwrap();
     }
 
     #[test]
-    fn deletion_blackbox() {
-        use rand::prelude::*;
+    fn plain_inserts_take_no_handle_slots() {
+        let mut tree = RBTree::new();
+        for val in 0..1000 {
+            tree.insert(val);
+        }
+        tree.lower_bound_mut(&2000).insert_before(2000);
+        assert!(tree.handles.slots.is_empty());
+
+        // With no handles out, split and join skip walking the moved elements
+        let mut upper = tree.split_off(&500);
+        let pivot = upper.pop_first().unwrap();
+        let mut joined = RBTree::join(tree, pivot, upper);
+        assert!(joined.handles.slots.is_empty());
+
+        let handle = joined.lower_bound_mut(&600).current_handle().unwrap();
+        assert_eq!(joined.handles.slots.len(), 1);
+        assert_eq!(joined.remove_by_handle(handle), Ok(600));
+        assert!(joined.handles.is_empty());
+        joined.validate().unwrap();
+    }
 
-        let mut tree = RBTree::<i32>::new();
+    #[test]
+    fn validate_after_every_operation() {
+        use rand::prelude::*;
 
         let mut rng = rand::rng();
+        let mut tree = RBTree::new();
+        let mut handles = Vec::new();
 
-        let items: Vec<i32> = (0..50).map(|_| rng.random_range(-100..100)).collect();
+        for _ in 0..2000 {
+            match rng.random_range(0..4) {
+                0 | 1 => handles.push(tree.insert_with_handle(rng.random_range(0..300))),
+                2 => {
+                    tree.delete(&rng.random_range(0..300));
+                }
+                _ => {
+                    if !handles.is_empty() {
+                        let handle = handles.swap_remove(rng.random_range(0..handles.len()));
+                        let _ = tree.remove_by_handle(handle);
+                    }
+                }
+            }
 
-        for item in &items {
-            tree.insert(*item);
+            let stats = tree.validate().unwrap();
+            assert_eq!(stats.len, tree.len());
+            assert!(stats.height <= 2 * stats.black_height + 1);
         }
+    }
 
-        let indices: Vec<usize> = (0..10).map(|_| rng.random_range(0..items.len())).collect();
+    #[test]
+    fn validate_names_broken_invariants() {
+        let empty = RBTree::<i32>::new();
+        assert_eq!(empty.validate().unwrap().height, 0);
 
-        for index in indices {
-            tree.delete(&items[index]);
-        }
+        let tree: RBTree<i32> = RBTree::from_sorted_iter(0..7);
+        let stats = tree.validate().unwrap();
+        assert_eq!(
+            (stats.height, stats.black_height, stats.red_nodes),
+            (3, 3, 0)
+        );
 
-        let tree_vec = tree.in_order_vec();
-        assert!(tree_vec.is_sorted());
+        unsafe {
+            let root = tree.root.into_node();
+            let left = (*root.as_ptr()).left.into_node();
+            let left_left = (*left.as_ptr()).left.into_node();
 
-        let max_height = unsafe { tree.root.into_node().as_ref().height() };
-        let min_height = unsafe { tree.root.into_node().as_ref().min_height() };
+            (*root.as_ptr()).color = Color::Red;
+            assert_eq!(tree.validate(), Err(RbViolation::RedRoot { root: 3 }));
+            (*root.as_ptr()).color = Color::Black;
 
-        assert!((max_height as f32 / min_height as f32) < 2.0);
+            (*left.as_ptr()).color = Color::Red;
+            assert_eq!(
+                tree.validate(),
+                Err(RbViolation::BlackHeightMismatch {
+                    node: 3,
+                    left: 1,
+                    right: 2
+                })
+            );
+            (*left_left.as_ptr()).color = Color::Red;
+            assert_eq!(
+                tree.validate(),
+                Err(RbViolation::RedChildOfRed {
+                    parent: 1,
+                    child: 0
+                })
+            );
+            (*left.as_ptr()).color = Color::Black;
+            (*left_left.as_ptr()).color = Color::Black;
+
+            (*left_left.as_ptr()).parent = tree.root;
+            assert_eq!(tree.validate(), Err(RbViolation::WrongParent { node: 0 }));
+            (*left_left.as_ptr()).parent = super::Link::Real(left);
+
+            (*left_left.as_ptr()).value = 2;
+            assert_eq!(
+                tree.validate(),
+                Err(RbViolation::OutOfOrder {
+                    before: 2,
+                    after: 1
+                })
+            );
+            (*left_left.as_ptr()).value = 0;
+
+            (*left.as_ptr()).size = 4;
+            assert_eq!(
+                tree.validate(),
+                Err(RbViolation::WrongSize {
+                    node: 1,
+                    stored: 4,
+                    actual: 3
+                })
+            );
+            (*left.as_ptr()).size = 3;
+
+            (*tree.nil.as_ptr()).parent = Some(left);
+            assert_eq!(tree.validate(), Err(RbViolation::NilParentSet));
+            (*tree.nil.as_ptr()).parent = None;
+        }
+
+        assert!(tree.validate().is_ok());
     }
 
     #[test]
-    fn simple_fuzzy_search() {
-        let mut tree: RBTree<i32> = RBTree::new();
-        tree.insert(1);
-        tree.insert(2);
-        tree.insert(5);
-        tree.insert(0);
+    fn ascii_render() {
+        let tree: RBTree<i32> = [1, 2, 3, 4].into_iter().collect();
+
+        let mut rendered = String::new();
+        tree.render(&mut rendered).unwrap();
+        assert_eq!(
+            rendered,
+            "+- 2 # Black - \n   +- 1 # Black - L\n   +- 3 # Black - R\n      +- 4 # Red - R\n"
+        );
+        assert_eq!(tree.display().to_string(), rendered);
+        assert_eq!(RBTree::<i32>::new().display().to_string(), "");
+    }
 
-        assert_eq!(tree.get_nearest(&-1), Some(&0));
+    #[test]
+    fn graphviz_export() {
+        let tree: RBTree<i32> = [1, 2, 3].into_iter().collect();
+        assert_eq!(
+            tree.to_dot(),
+            "digraph RBTree {\n    node [style=filled, fontcolor=white];\n    \
+             n0 [label=\"2\", fillcolor=black];\n    n0 -> n1;\n    n0 -> n2;\n    \
+             n1 [label=\"1\", fillcolor=red];\n    n2 [label=\"3\", fillcolor=red];\n}\n"
+        );
+
+        let with_nil = tree.to_dot_with_nil_leaves();
+        assert_eq!(with_nil.matches("shape=point").count(), tree.len() + 1);
+
+        let mut words = RBTree::new();
+        words.insert(String::from("say \"hi\""));
+        assert!(words.to_dot().contains(r#"label="\"say \\\"hi\\\"\"""#));
     }
 
     #[test]
-    fn complex_fuzzy_search() {
-        let mut tree: RBTree<i32> = RBTree::new();
-        tree.insert(1);
-        tree.insert(2);
-        tree.insert(5);
-        tree.insert(0);
-        tree.insert(-4);
-        tree.insert(7);
-        tree.insert(12);
-        tree.insert(-3);
+    fn trace_insert_fix_up() {
+        let mut tree = RBTree::new();
+        for val in [0, 2, -3, -1] {
+            tree.insert(val);
+        }
 
-        tree.print();
-        assert_eq!(tree.get_nearest(&8), Some(&7));
+        tree.start_trace();
+        tree.insert(-2);
+        assert_eq!(
+            tree.stop_trace(),
+            vec![
+                Event::InsertCase {
+                    case: 2,
+                    node: -2,
+                    mirrored: true
+                },
+                Event::RotateRight { node: -1 },
+                Event::InsertCase {
+                    case: 3,
+                    node: -1,
+                    mirrored: true
+                },
+                Event::Recolor {
+                    node: -2,
+                    color: Color::Black
+                },
+                Event::Recolor {
+                    node: -3,
+                    color: Color::Red
+                },
+                Event::RotateLeft { node: -3 },
+            ]
+        );
+
+        tree.insert(5);
+        assert!(tree.take_trace().is_empty());
     }
 
     #[test]
-    fn fuzzy_search_returns_nothing() {
-        let tree: RBTree<i32> = RBTree::new();
+    fn trace_delete_fix_up() {
+        let mut tree = RBTree::new();
+        for val in [0, -10, 10, -12, 5, 3, 8] {
+            tree.insert(val);
+        }
 
-        assert_eq!(tree.get_nearest(&10), None);
+        tree.start_trace();
+        assert!(tree.delete(&3));
+
+        let case = |case| Event::DeleteCase {
+            case,
+            node: 5,
+            mirrored: false,
+        };
+        let recolor = |node, color| Event::Recolor { node, color };
+        assert_eq!(
+            tree.take_trace(),
+            vec![
+                case(3),
+                recolor(8, Color::Black),
+                recolor(10, Color::Red),
+                Event::RotateRight { node: 10 },
+                case(4),
+                recolor(8, Color::Red),
+                recolor(5, Color::Black),
+                recolor(10, Color::Black),
+                Event::RotateLeft { node: 5 },
+            ]
+        );
     }
 
     #[test]
-    fn fuzzy_search_adjusts_for_closer_item() {
-        let mut tree: RBTree<i32> = RBTree::new();
+    fn compares_by_contents() {
+        let ascending: RBTree<i32> = (0..50).collect();
+        let mut descending = RBTree::new();
+        for val in (0..60).rev() {
+            descending.insert(val);
+        }
+        for val in 50..60 {
+            descending.delete(&val);
+        }
 
-        tree.insert(0);
-        tree.insert(5);
-        tree.insert(-1);
+        assert_eq!(ascending, descending);
+        assert_eq!(ascending.cmp(&descending), std::cmp::Ordering::Equal);
 
-        assert_eq!(tree.get_nearest(&4), Some(&5));
+        let mut set = HashSet::new();
+        set.insert(ascending.clone());
+        assert!(set.contains(&descending));
+
+        descending.insert(50);
+        assert_ne!(ascending, descending);
+        assert!(ascending < descending);
+        assert!(RBTree::<i32>::from_iter([1, 5]) > RBTree::from_iter([1, 2, 3]));
+
+        let small: RBTree<i32> = [3, 1, 2].into_iter().collect();
+        assert_eq!(format!("{small:?}"), "{1, 2, 3}");
+        assert_eq!(format!("{:?}", RBTree::<i32>::new()), "{}");
     }
 }
@@ -0,0 +1,464 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
+
+use super::rbtree::{self, Node, RBTree};
+
+#[derive(Debug, Clone)]
+struct MapEntry<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for MapEntry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for MapEntry<K, V> {}
+
+impl<K: Ord, V> PartialOrd for MapEntry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for MapEntry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+pub struct RBTreeMap<K: Ord, V> {
+    tree: RBTree<MapEntry<K, V>>,
+}
+
+impl<K: Ord, V> Default for RBTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> RBTreeMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: RBTree::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn find<Q: Ord + ?Sized>(&self, key: &Q) -> Option<NonNull<Node<MapEntry<K, V>>>>
+    where
+        K: Borrow<Q>,
+    {
+        unsafe { self.tree.search_by(|entry| key.cmp(entry.key.borrow())) }
+    }
+
+    /// Returns the previous value if the key was already present
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value.value })
+    }
+
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key)
+            .map(|node| unsafe { &mut (*node.as_ptr()).value.value })
+    }
+
+    pub fn contains_key<Q: Ord + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        self.tree
+            .remove_by(|entry| key.cmp(entry.key.borrow()))
+            .map(|entry| entry.value)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            entries: self.tree.iter(),
+        }
+    }
+
+    pub fn range<Q: Ord + ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+    {
+        Range {
+            entries: self.tree.range_by(
+                |entry| match range.start_bound() {
+                    Bound::Included(start) => entry.key.borrow() < start,
+                    Bound::Excluded(start) => entry.key.borrow() <= start,
+                    Bound::Unbounded => false,
+                },
+                |entry| match range.end_bound() {
+                    Bound::Included(end) => entry.key.borrow() > end,
+                    Bound::Excluded(end) => entry.key.borrow() >= end,
+                    Bound::Unbounded => false,
+                },
+            ),
+        }
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.find(&key) {
+            Some(node) => Entry::Occupied(OccupiedEntry { map: self, node }),
+            None => Entry::Vacant(VacantEntry { map: self, key }),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Clone for RBTreeMap<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<K: Ord + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for RBTreeMap<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, K: Ord, V> {
+    entries: rbtree::Iter<'a, MapEntry<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| (&entry.key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next_back()
+            .map(|entry| (&entry.key, &entry.value))
+    }
+}
+
+impl<'a, K: Ord, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K: Ord, V> IntoIterator for &'a RBTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Range<'a, K: Ord, V> {
+    entries: rbtree::Range<'a, MapEntry<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| (&entry.key, &entry.value))
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next_back()
+            .map(|entry| (&entry.key, &entry.value))
+    }
+}
+
+pub enum Entry<'a, K: Ord, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify(mut self, modify: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            modify(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    map: &'a mut RBTreeMap<K, V>,
+    node: NonNull<Node<MapEntry<K, V>>>,
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node.as_ptr()).value.key }
+    }
+
+    pub fn get(&self) -> &V {
+        unsafe { &(*self.node.as_ptr()).value.value }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*self.node.as_ptr()).value.value }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut (*self.node.as_ptr()).value.value }
+    }
+
+    /// Returns the value that was replaced
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        // SAFETY: the entry was found in `map.tree` and its `&mut` borrow of the map keeps
+        // anything else from removing the node in the meantime
+        unsafe { self.map.tree.remove_node(self.node).value }
+    }
+}
+
+pub struct VacantEntry<'a, K: Ord, V> {
+    map: &'a mut RBTreeMap<K, V>,
+    key: K,
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        unsafe {
+            let node = self.map.tree.unsafe_insert(MapEntry {
+                key: self.key,
+                value,
+            });
+            &mut (*node.as_ptr()).value.value
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, RBTreeMap};
+
+    #[test]
+    fn insert_returns_previous_value() {
+        let mut map = RBTreeMap::new();
+
+        assert_eq!(map.insert(3, "three"), None);
+        assert_eq!(map.insert(1, "one"), None);
+        assert_eq!(map.insert(3, "tres"), Some("three"));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&3), Some(&"tres"));
+        assert_eq!(map.get(&1), Some(&"one"));
+        assert_eq!(map.get(&2), None);
+    }
+
+    #[test]
+    fn get_mut_modifies_in_place() {
+        let mut map = RBTreeMap::new();
+        map.insert(10, 1);
+
+        *map.get_mut(&10).unwrap() += 5;
+
+        assert_eq!(map.get(&10), Some(&6));
+        assert_eq!(map.get_mut(&11), None);
+    }
+
+    #[test]
+    fn remove_by_key() {
+        let mut map = RBTreeMap::new();
+        for key in 0..20 {
+            map.insert(key, key * 2);
+        }
+
+        assert_eq!(map.remove(&7), Some(14));
+        assert_eq!(map.remove(&7), None);
+        assert_eq!(map.len(), 19);
+
+        for key in (0..20).filter(|key| *key != 7) {
+            assert_eq!(map.get(&key), Some(&(key * 2)));
+        }
+    }
+
+    #[test]
+    fn lookup_with_borrowed_key() {
+        let mut map = RBTreeMap::new();
+        map.insert(String::from("apple"), 1);
+        map.insert(String::from("pear"), 2);
+
+        assert_eq!(map.get("pear"), Some(&2));
+        assert!(map.contains_key("apple"));
+        assert_eq!(map.remove("apple"), Some(1));
+        assert!(!map.contains_key("apple"));
+    }
+
+    #[test]
+    fn entry_counts_words() {
+        let mut map: RBTreeMap<&str, u32> = RBTreeMap::new();
+
+        for word in "the cat and the dog and the bird".split(' ') {
+            map.entry(word).and_modify(|count| *count += 1).or_insert(1);
+        }
+
+        assert_eq!(map.get("the"), Some(&3));
+        assert_eq!(map.get("and"), Some(&2));
+        assert_eq!(map.get("bird"), Some(&1));
+        assert_eq!(map.len(), 5);
+    }
+
+    #[test]
+    fn occupied_entry_remove() {
+        let mut map = RBTreeMap::new();
+        map.insert(1, 'a');
+        map.insert(2, 'b');
+
+        let Entry::Occupied(entry) = map.entry(1) else {
+            panic!("Entry should be occupied");
+        };
+        assert_eq!(entry.remove(), 'a');
+
+        assert!(matches!(map.entry(1), Entry::Vacant(_)));
+        assert_eq!(*map.entry(1).or_default(), '\0');
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn iterates_in_key_order() {
+        let mut map = RBTreeMap::new();
+        for key in [4, 1, 3, 2] {
+            map.insert(key, key * 10);
+        }
+
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![(&1, &10), (&2, &20), (&3, &30), (&4, &40)]
+        );
+        assert_eq!(
+            map.keys().rev().copied().collect::<Vec<_>>(),
+            vec![4, 3, 2, 1]
+        );
+        assert_eq!(map.values().sum::<i32>(), 100);
+    }
+
+    #[test]
+    fn range_over_keys() {
+        let mut map = RBTreeMap::new();
+        for key in 0..10 {
+            map.insert(key, key.to_string());
+        }
+
+        assert_eq!(
+            map.range(3..6).map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            map.range(8..)
+                .rev()
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["9", "8"]
+        );
+    }
+
+    #[test]
+    fn values_need_not_be_clone_or_debug() {
+        let mut map: RBTreeMap<&str, Box<dyn Fn(i32) -> i32>> = RBTreeMap::new();
+        map.insert("double", Box::new(|x| x * 2));
+        map.insert("negate", Box::new(|x| -x));
+
+        assert_eq!(map.get("double").unwrap()(21), 42);
+        assert_eq!(map.remove("negate").unwrap()(5), -5);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn clone_and_debug() {
+        let mut map = RBTreeMap::new();
+        map.insert(2, "two");
+        map.insert(1, "one");
+
+        let mut copy = map.clone();
+        copy.insert(3, "three");
+        assert_eq!(format!("{map:?}"), r#"{1: "one", 2: "two"}"#);
+        assert_eq!(copy.len(), 3);
+    }
+}
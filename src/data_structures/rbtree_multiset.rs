@@ -0,0 +1,203 @@
+use std::borrow::Borrow;
+
+use super::rbtree_map::{self, RBTreeMap};
+
+/// Ordered multiset keeping one node per distinct value along with its multiplicity
+pub struct RBMultiset<T: Ord + std::fmt::Debug + Clone> {
+    counts: RBTreeMap<T, usize>,
+    len: usize,
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> Default for RBMultiset<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + std::fmt::Debug + Clone> RBMultiset<T> {
+    pub fn new() -> Self {
+        Self {
+            counts: RBTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of elements, counting repeats
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn distinct_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the new multiplicity of `element`
+    pub fn insert(&mut self, element: T) -> usize {
+        self.insert_many(element, 1)
+    }
+
+    pub fn insert_many(&mut self, element: T, copies: usize) -> usize {
+        if copies == 0 {
+            return self.count(&element);
+        }
+
+        self.len += copies;
+        let count = self.counts.entry(element).or_insert(0);
+        *count += copies;
+        *count
+    }
+
+    pub fn count<Q: Ord + ?Sized>(&self, element: &Q) -> usize
+    where
+        T: Borrow<Q>,
+    {
+        self.counts.get(element).copied().unwrap_or(0)
+    }
+
+    pub fn contains<Q: Ord + ?Sized>(&self, element: &Q) -> bool
+    where
+        T: Borrow<Q>,
+    {
+        self.counts.contains_key(element)
+    }
+
+    /// Removes a single copy, returning whether one was present
+    pub fn remove_one<Q: Ord + ?Sized>(&mut self, element: &Q) -> bool
+    where
+        T: Borrow<Q>,
+    {
+        match self.counts.get_mut(element) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.counts.remove(element);
+            }
+            None => return false,
+        }
+
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every copy, returning how many there were
+    pub fn remove_all<Q: Ord + ?Sized>(&mut self, element: &Q) -> usize
+    where
+        T: Borrow<Q>,
+    {
+        let removed = self.counts.remove(element).unwrap_or(0);
+        self.len -= removed;
+        removed
+    }
+
+    /// Every element in order, repeated by its multiplicity
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            counts: self.counts.iter(),
+            current: None,
+            remaining: self.len,
+        }
+    }
+
+    /// Each distinct element in order along with its multiplicity
+    pub fn iter_counts(&self) -> impl DoubleEndedIterator<Item = (&T, usize)> + ExactSizeIterator {
+        self.counts.iter().map(|(element, count)| (element, *count))
+    }
+}
+
+pub struct Iter<'a, T: Ord + std::fmt::Debug + Clone> {
+    counts: rbtree_map::Iter<'a, T, usize>,
+    current: Option<(&'a T, usize)>,
+    remaining: usize,
+}
+
+impl<'a, T: Ord + std::fmt::Debug + Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (element, copies_left) = match self.current {
+            Some((element, copies_left)) if copies_left > 0 => (element, copies_left),
+            _ => {
+                let (element, count) = self.counts.next()?;
+                (element, *count)
+            }
+        };
+
+        self.current = Some((element, copies_left - 1));
+        self.remaining -= 1;
+        Some(element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord + std::fmt::Debug + Clone> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: Ord + std::fmt::Debug + Clone> IntoIterator for &'a RBMultiset<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RBMultiset;
+
+    #[test]
+    fn counts_duplicates() {
+        let mut set = RBMultiset::new();
+
+        assert_eq!(set.insert(3), 1);
+        assert_eq!(set.insert(1), 1);
+        assert_eq!(set.insert(3), 2);
+        assert_eq!(set.insert_many(7, 4), 4);
+
+        assert_eq!(set.count(&3), 2);
+        assert_eq!(set.count(&7), 4);
+        assert_eq!(set.count(&5), 0);
+        assert_eq!(set.len(), 7);
+        assert_eq!(set.distinct_len(), 3);
+    }
+
+    #[test]
+    fn remove_one_and_remove_all() {
+        let mut set = RBMultiset::new();
+        set.insert_many("a", 3);
+        set.insert("b");
+
+        assert!(set.remove_one("a"));
+        assert_eq!(set.count("a"), 2);
+        assert!(set.remove_one("b"));
+        assert!(!set.contains("b"));
+        assert!(!set.remove_one("b"));
+
+        assert_eq!(set.remove_all("a"), 2);
+        assert_eq!(set.remove_all("a"), 0);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn iter_repeats_by_multiplicity() {
+        let mut set = RBMultiset::new();
+        for val in [5, 1, 5, 3, 1, 5] {
+            set.insert(val);
+        }
+
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![1, 1, 3, 5, 5, 5]
+        );
+        assert_eq!(set.iter().len(), 6);
+        assert_eq!(
+            set.iter_counts().collect::<Vec<_>>(),
+            vec![(&1, 2), (&3, 1), (&5, 3)]
+        );
+    }
+}
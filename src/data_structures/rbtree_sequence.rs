@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+
+use super::rbtree::{self, Compare, RBTree};
+
+/// Ties every pair of elements, so the tree keeps them in the order they were placed in and
+/// ordinary insertion appends
+#[derive(Debug, Clone, Copy, Default)]
+struct Positional;
+
+impl<T> Compare<T> for Positional {
+    fn compare(&self, _lhs: &T, _rhs: &T) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+/// Sequence kept in an [`RBTree`] where the position of an element is implied by the subtree
+/// sizes instead of by comparing elements. Positional edits, splits and concatenation are all
+/// O(log n).
+#[derive(Clone)]
+pub struct RBSequence<T: std::fmt::Debug + Clone> {
+    tree: RBTree<T, (), Positional>,
+}
+
+impl<T: std::fmt::Debug + Clone> Default for RBSequence<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug + Clone> RBSequence<T> {
+    pub fn new() -> Self {
+        Self {
+            tree: RBTree::with_comparator(Positional),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.select(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        // Positions do not depend on the values, so changing one in place is fine
+        unsafe {
+            self.tree
+                .select_node(index)
+                .map(|node| &mut (*node.as_ptr()).value)
+        }
+    }
+
+    /// Inserts `element` so it ends up at `index`, shifting later elements back. Panics if
+    /// `index > len`.
+    pub fn insert_at(&mut self, index: usize, element: T) {
+        assert!(
+            index <= self.len(),
+            "insertion index {index} is past the end of a sequence of length {}",
+            self.len()
+        );
+
+        self.tree.insert_at(index, element);
+    }
+
+    /// Removes and returns the element at `index`, shifting later elements forward. Panics if
+    /// `index >= len`.
+    pub fn remove_at(&mut self, index: usize) -> T {
+        unsafe {
+            let Some(node) = self.tree.select_node(index) else {
+                panic!(
+                    "removal index {index} is out of bounds for a sequence of length {}",
+                    self.len()
+                );
+            };
+            self.tree.remove_node(node)
+        }
+    }
+
+    pub fn push_front(&mut self, element: T) {
+        self.insert_at(0, element);
+    }
+
+    pub fn push_back(&mut self, element: T) {
+        self.insert_at(self.len(), element);
+    }
+
+    /// The first `index` elements and the rest. Panics if `index > len`.
+    pub fn split_at(mut self, index: usize) -> (Self, Self) {
+        assert!(
+            index <= self.len(),
+            "split index {index} is past the end of a sequence of length {}",
+            self.len()
+        );
+
+        let back = self.tree.split_off_at(index);
+        (self, Self { tree: back })
+    }
+
+    /// The elements of `self` followed by the elements of `other`
+    pub fn concat(mut self, other: Self) -> Self {
+        self.tree.append(other.tree);
+        self
+    }
+
+    pub fn iter(&self) -> rbtree::Iter<'_, T> {
+        self.tree.iter()
+    }
+}
+
+impl<T: std::fmt::Debug + Clone> std::fmt::Debug for RBSequence<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: std::fmt::Debug + Clone> IntoIterator for &'a RBSequence<T> {
+    type Item = &'a T;
+    type IntoIter = rbtree::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: std::fmt::Debug + Clone> IntoIterator for RBSequence<T> {
+    type Item = T;
+    type IntoIter = rbtree::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tree.into_iter()
+    }
+}
+
+impl<T: std::fmt::Debug + Clone> FromIterator<T> for RBSequence<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // Every order is sorted when all elements tie
+        Self {
+            tree: RBTree::from_sorted_iter(iter),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug + Clone> Extend<T> for RBSequence<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.push_back(element);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RBSequence;
+
+    fn assert_valid<T: std::fmt::Debug + Clone>(sequence: &RBSequence<T>) {
+        sequence.tree.validate().unwrap();
+    }
+
+    #[test]
+    fn positional_edits_match_vec() {
+        use rand::prelude::*;
+
+        let mut rng = rand::rng();
+        let mut sequence = RBSequence::new();
+        let mut expected = Vec::new();
+
+        for step in 0..2000 {
+            if expected.is_empty() || rng.random_bool(2.0 / 3.0) {
+                let index = rng.random_range(0..=expected.len());
+                sequence.insert_at(index, step);
+                expected.insert(index, step);
+            } else {
+                let index = rng.random_range(0..expected.len());
+                assert_eq!(sequence.remove_at(index), expected.remove(index));
+            }
+            assert_valid(&sequence);
+        }
+
+        assert_eq!(sequence.len(), expected.len());
+        assert!(sequence.iter().eq(expected.iter()));
+        for (index, value) in expected.iter().enumerate() {
+            assert_eq!(sequence.get(index), Some(value));
+        }
+        assert_eq!(sequence.get(expected.len()), None);
+    }
+
+    #[test]
+    fn split_and_concat() {
+        let sequence: RBSequence<usize> = (0..100).collect();
+
+        for index in [0, 1, 37, 99, 100] {
+            let (front, back) = sequence.clone().split_at(index);
+            assert_valid(&front);
+            assert_valid(&back);
+            assert!(front.iter().copied().eq(0..index));
+            assert!(back.iter().copied().eq(index..100));
+
+            let rejoined = back.concat(front);
+            assert_valid(&rejoined);
+            assert!(rejoined.iter().copied().eq((index..100).chain(0..index)));
+        }
+
+        let short: RBSequence<usize> = (100..103).collect();
+        let joined = short.clone().concat(sequence.clone());
+        assert_valid(&joined);
+        assert!(joined.iter().copied().eq((100..103).chain(0..100)));
+        let joined = sequence.concat(short);
+        assert_valid(&joined);
+        assert!(joined.iter().copied().eq(0..103));
+    }
+
+    #[test]
+    fn split_and_concat_after_single_edits() {
+        let mut sequence = RBSequence::new();
+        for val in 0..50 {
+            sequence.push_back(val);
+        }
+        sequence.extend(50..100);
+        sequence.insert_at(0, 1000);
+        assert_eq!(sequence.remove_at(0), 1000);
+
+        let (front, back) = sequence.split_at(40);
+        assert_valid(&front);
+        assert_valid(&back);
+        let mut rejoined = back.concat(front);
+        assert_valid(&rejoined);
+        rejoined.extend(100..110);
+        assert!(
+            rejoined
+                .iter()
+                .copied()
+                .eq((40..100).chain(0..40).chain(100..110))
+        );
+    }
+
+    #[test]
+    fn text_buffer_edits() {
+        let mut buffer: RBSequence<char> = "hello world".chars().collect();
+        buffer.insert_at(5, ',');
+        *buffer.get_mut(0).unwrap() = 'H';
+        buffer.remove_at(buffer.len() - 1);
+        buffer.push_back('!');
+        assert_eq!(buffer.iter().collect::<String>(), "Hello, worl!");
+        assert_eq!(format!("{:?}", buffer.split_at(3).0), "['H', 'e', 'l']");
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_past_the_end_panics() {
+        let mut sequence: RBSequence<i32> = (0..3).collect();
+        sequence.insert_at(4, 0);
+    }
+}
@@ -1,7 +1,15 @@
 #![allow(unused)]
 pub mod data_structures {
+    pub mod concurrent_rbtree;
     pub mod dcel;
+    pub mod indexed_priority_queue;
+    pub mod interval_tree;
+    pub mod persistent_rbtree;
+    pub mod range_set;
     pub mod rbtree;
+    pub mod rbtree_map;
+    pub mod rbtree_multiset;
+    pub mod rbtree_sequence;
     pub mod ring_buffer;
     pub mod skip_list;
     pub mod tree;
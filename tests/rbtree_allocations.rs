@@ -0,0 +1,268 @@
+//! Leak and double-free checks for `RBTree`. They live in their own test binary because the
+//! counting allocator below replaces the global allocator of whatever binary it is linked into.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use cool_utils::data_structures::rbtree::RBTree;
+use cool_utils::data_structures::rbtree_sequence::RBSequence;
+use rand::prelude::*;
+
+/// Counts live heap allocations per thread, so each test can check that the nodes it created
+/// were all freed again
+mod allocation_tracking {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    struct TrackingAllocator;
+
+    thread_local! {
+        static LIVE_ALLOCATIONS: Cell<isize> = const { Cell::new(0) };
+        static TOTAL_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for TrackingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = LIVE_ALLOCATIONS.try_with(|live| live.set(live.get() + 1));
+            let _ = TOTAL_ALLOCATIONS.try_with(|total| total.set(total.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE_ALLOCATIONS.try_with(|live| live.set(live.get() - 1));
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+    pub fn live_allocations() -> isize {
+        LIVE_ALLOCATIONS.with(|live| live.get())
+    }
+
+    /// Allocations made so far, including those already freed again
+    pub fn total_allocations() -> usize {
+        TOTAL_ALLOCATIONS.with(|total| total.get())
+    }
+}
+
+use allocation_tracking::{live_allocations, total_allocations};
+
+/// Counts how many copies exist, so double drops and leaked values both show up
+#[derive(Debug)]
+struct Tracked {
+    value: i32,
+    live: Rc<Cell<isize>>,
+}
+
+impl Tracked {
+    fn new(value: i32, live: &Rc<Cell<isize>>) -> Self {
+        live.set(live.get() + 1);
+        Tracked {
+            value,
+            live: live.clone(),
+        }
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        Tracked::new(self.value, &self.live)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.live.set(self.live.get() - 1);
+    }
+}
+
+impl PartialEq for Tracked {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Tracked {}
+
+impl PartialOrd for Tracked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Tracked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+#[test]
+fn insert_delete_drop_frees_everything() {
+    let mut rng = rand::rng();
+    let items: Vec<i32> = (0..500).map(|_| rng.random_range(-200..200)).collect();
+    let live = Rc::new(Cell::new(0));
+    let allocations_before = live_allocations();
+
+    {
+        let mut tree = RBTree::new();
+        for item in &items {
+            tree.insert(Tracked::new(*item, &live));
+        }
+        for item in &items[..250] {
+            tree.delete(&Tracked::new(*item, &live));
+        }
+        assert_eq!(live.get(), tree.len() as isize);
+    }
+
+    assert_eq!(live.get(), 0);
+    assert_eq!(live_allocations(), allocations_before);
+}
+
+#[test]
+fn dropping_a_long_spine_does_not_recurse() {
+    let allocations_before = live_allocations();
+
+    {
+        let mut tree = RBTree::new();
+        for val in 0..200_000 {
+            tree.insert(val);
+        }
+    }
+
+    assert_eq!(live_allocations(), allocations_before);
+}
+
+#[test]
+fn into_iter_frees_nodes() {
+    let live = Rc::new(Cell::new(0));
+    let allocations_before = live_allocations();
+
+    {
+        let mut tree = RBTree::new();
+        for val in 0..100 {
+            tree.insert(Tracked::new(val, &live));
+        }
+        let mut iter = tree.into_iter();
+        iter.next();
+        iter.next_back();
+        assert_eq!(live.get(), 98);
+    }
+
+    assert_eq!(live.get(), 0);
+    assert_eq!(live_allocations(), allocations_before);
+}
+
+#[test]
+fn clone_frees_both_copies() {
+    let live = Rc::new(Cell::new(0));
+    let allocations_before = live_allocations();
+
+    {
+        let mut tracked = RBTree::new();
+        for val in 0..50 {
+            tracked.insert(Tracked::new(val, &live));
+        }
+        let tracked_clone = tracked.clone();
+        assert_eq!(live.get(), 100);
+        drop(tracked);
+        assert_eq!(tracked_clone.len(), 50);
+        assert_eq!(live.get(), 50);
+    }
+
+    assert_eq!(live.get(), 0);
+    assert_eq!(live_allocations(), allocations_before);
+}
+
+#[test]
+fn set_operations_free_what_they_drop() {
+    let live = Rc::new(Cell::new(0));
+    let allocations_before = live_allocations();
+
+    {
+        let build = |values: std::ops::Range<i32>, step: i32| {
+            let mut tree = RBTree::new();
+            for val in values {
+                tree.insert(Tracked::new(val * step, &live));
+            }
+            tree
+        };
+
+        let union = build(0..100, 2).union(build(0..100, 3));
+        let intersection = build(0..100, 2).intersection(build(0..100, 3));
+        let difference = build(0..100, 2).difference(build(0..100, 3));
+        let mut lower = build(0..100, 1);
+        let upper = lower.split_off(&Tracked::new(40, &live));
+        assert_eq!(
+            live.get() as usize,
+            union.len() + intersection.len() + difference.len() + lower.len() + upper.len()
+        );
+    }
+
+    assert_eq!(live.get(), 0);
+    assert_eq!(live_allocations(), allocations_before);
+}
+
+#[test]
+fn union_keeps_elements_from_self() {
+    fn value(tracked: &Tracked) -> i32 {
+        tracked.value
+    }
+
+    let live = Rc::new(Cell::new(0));
+    {
+        let mut ours = RBTree::by_key(value);
+        let mut theirs = RBTree::by_key(value);
+        for val in 0..20 {
+            ours.insert(Tracked::new(val * 2, &live));
+            theirs.insert(Tracked::new(val * 3, &live));
+        }
+
+        let ours_marker = Rc::new(Cell::new(0));
+        ours.insert(Tracked::new(1000, &ours_marker));
+        theirs.insert(Tracked::new(1000, &live));
+
+        let union = ours.union(theirs);
+        assert_eq!(union.len(), 34);
+        let last = union.iter().next_back().unwrap();
+        assert!(Rc::ptr_eq(&last.live, &ours_marker));
+    }
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn split_and_join_skip_the_handle_walk_without_handles() {
+    let mut tree = RBTree::new();
+    let allocations_before = live_allocations();
+    for val in 0..100_000 {
+        tree.insert(val);
+    }
+    // One node per element and no handle slots
+    assert_eq!(live_allocations() - allocations_before, 100_000);
+
+    // Retiring handles walks the moved elements with a heap-allocated stack, so the only
+    // allocations left are the new tree and the pivot node
+    let total_before = total_allocations();
+    let mut upper = tree.split_off(&50_000);
+    let pivot = upper.pop_first().unwrap();
+    let joined = RBTree::join(tree, pivot, upper);
+    assert_eq!(total_allocations() - total_before, 2);
+    assert_eq!(joined.len(), 100_000);
+}
+
+#[test]
+fn sequence_split_and_concat_skip_the_handle_walk() {
+    let mut sequence = RBSequence::new();
+    for val in 0..50_000 {
+        sequence.push_back(val);
+    }
+    sequence.extend(50_000..100_000);
+
+    // Only the new back half allocates
+    let total_before = total_allocations();
+    let (front, back) = sequence.split_at(30_000);
+    let sequence = back.concat(front);
+    assert_eq!(total_allocations() - total_before, 1);
+    assert_eq!(sequence.get(0), Some(&30_000));
+}